@@ -0,0 +1,211 @@
+use crate::datetime::DateSegments;
+use crate::headers::Metadata;
+use crate::stack::GridStack;
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::io;
+
+// what to do when an hour of a period is missing, either the whole grid or a single cell
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MissingPolicy {
+    Fail,
+    Skip,             // total whatever hours are available
+    MinFraction(f64), // total the available hours if at least this fraction of the period is present
+}
+
+// an hourly XMRG grid valid at hour H holds the precipitation that fell from H-1 to H,
+// so an N hour total valid at E is the sum of the grids valid from E-N+1 through E
+#[derive(Debug, Copy, Clone)]
+pub struct Accumulation {
+    hours: i64,
+    boundary_hour: i64,
+    policy: MissingPolicy,
+}
+
+impl Accumulation {
+    // periods end on boundary_hour and every `hours` after it, e.g. 24 hours at 12 for a 12Z hydrologic day
+    pub fn new(hours: i64, boundary_hour: i64, policy: MissingPolicy) -> Self {
+        Accumulation {
+            hours,
+            boundary_hour,
+            policy,
+        }
+    }
+
+    pub fn calendar_day(policy: MissingPolicy) -> Self {
+        Self::new(24, 0, policy)
+    }
+
+    pub fn hours(&self) -> i64 {
+        self.hours
+    }
+
+    // the end of the period holding the hourly grid valid at time
+    pub fn period_end(&self, time: DateSegments) -> DateSegments {
+        let hour = time.hours_since_epoch();
+        let offset = (self.hours - (hour - self.boundary_hour).rem_euclid(self.hours)) % self.hours;
+        DateSegments::from_hours(hour + offset)
+    }
+
+    // totals every period that lies entirely within the stack, each valid at the end of its period
+    pub fn accumulate(&self, stack: &GridStack) -> io::Result<Vec<XmrgData>> {
+        if self.hours <= 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot accumulate over {} hours", self.hours),
+            ));
+        }
+
+        if stack.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let first = stack.times[0].hours_since_epoch();
+        let last = stack.times[stack.len() - 1].hours_since_epoch();
+
+        let mut end = self
+            .period_end(DateSegments::from_hours(first + self.hours - 1))
+            .hours_since_epoch();
+
+        let mut totals = Vec::new();
+        while end <= last {
+            let start = (end - self.hours + 1 - first) as usize;
+            let valid_time = DateSegments::from_hours(end);
+            let values = self.total(stack, start, valid_time)?;
+            let metadata = Metadata::for_product(valid_time, &self.process_flag(), &values);
+
+            totals.push(XmrgData::new(stack.header, Some(metadata), values));
+            end += self.hours;
+        }
+
+        Ok(totals)
+    }
+
+    fn process_flag(&self) -> String {
+        format!("ACC{:02}H", self.hours)
+    }
+
+    fn total(
+        &self,
+        stack: &GridStack,
+        start: usize,
+        valid_time: DateSegments,
+    ) -> io::Result<Vec<Vec<f64>>> {
+        let period = &stack.values[start..start + self.hours as usize];
+        let rows = stack.header.rows() as usize;
+        let columns = stack.header.columns() as usize;
+
+        (0..rows)
+            .map(|row| {
+                (0..columns)
+                    .map(|column| {
+                        let (sum, count) = period
+                            .iter()
+                            .map(|grid| grid[row][column])
+                            .filter(|value| *value >= 0.0)
+                            .fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+
+                        self.apply_policy(sum, count, valid_time, row, column)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn apply_policy(
+        &self,
+        sum: f64,
+        count: i64,
+        valid_time: DateSegments,
+        row: usize,
+        column: usize,
+    ) -> io::Result<f64> {
+        match self.policy {
+            MissingPolicy::Fail if count < self.hours => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} of {} hours are missing at row {}, column {} of the period ending {}",
+                    self.hours - count,
+                    self.hours,
+                    row,
+                    column,
+                    valid_time
+                ),
+            )),
+            MissingPolicy::Fail => Ok(sum),
+            MissingPolicy::Skip if count == 0 => Ok(MISSING),
+            MissingPolicy::Skip => Ok(sum),
+            MissingPolicy::MinFraction(fraction) => {
+                if count > 0 && count as f64 / self.hours as f64 >= fraction {
+                    Ok(sum)
+                } else {
+                    Ok(MISSING)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+
+    fn hourly_stack(values: &[f64]) -> GridStack {
+        let header = Header::new(10, 20, 1, 1);
+        let t0 = DateSegments::new(2020, 6, 1, 10);
+        let grids = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let data = XmrgData::new(header, None, vec![vec![*v]]);
+                (t0.add_hours(i as i64), data)
+            })
+            .collect();
+
+        GridStack::from_grids(grids).unwrap()
+    }
+
+    #[test]
+    fn period_end_test() {
+        let acc = Accumulation::new(24, 12, MissingPolicy::Skip);
+
+        assert_eq!(
+            acc.period_end(DateSegments::new(2020, 6, 1, 13)),
+            DateSegments::new(2020, 6, 2, 12)
+        );
+        assert_eq!(
+            acc.period_end(DateSegments::new(2020, 6, 1, 12)),
+            DateSegments::new(2020, 6, 1, 12)
+        );
+    }
+
+    #[test]
+    fn accumulate_aligned_periods_test() {
+        // hours valid 10Z through 18Z
+        let stack = hourly_stack(&[1.0, 1.0, 2.0, 2.0, 2.0, -999.0, 3.0, 3.0, 3.0]);
+
+        let skip = Accumulation::new(3, 0, MissingPolicy::Skip)
+            .accumulate(&stack)
+            .unwrap();
+
+        // periods end at 12Z, 15Z and 18Z, the 15Z period is missing its last hour
+        assert_eq!(skip.len(), 3);
+        assert_eq!(
+            skip[0].valid_time(),
+            Some(DateSegments::new(2020, 6, 1, 12))
+        );
+        assert_eq!(skip[0].values[0][0], 4.0);
+        assert_eq!(skip[1].values[0][0], 4.0);
+        assert_eq!(skip[2].values[0][0], 9.0);
+
+        let min = Accumulation::new(3, 0, MissingPolicy::MinFraction(1.0))
+            .accumulate(&stack)
+            .unwrap();
+        assert_eq!(min[1].values[0][0], MISSING);
+
+        let fail = Accumulation::new(3, 0, MissingPolicy::Fail).accumulate(&stack);
+        assert!(fail.is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::geo::Point;
+use crate::headers::Header;
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::io;
+
+// element-wise grid arithmetic. Grids with different windows are combined over the cells they share.
+// Only MISSING itself counts as missing here, so signed results such as differences between products
+// survive further operations, and a difference of exactly -999.0 is taken as missing. Signed results are
+// float only grids: the rest of the crate treats any negative value as missing, and write_xmrg and Int16
+// GeoTIFFs return an error for them rather than writing them as missing
+
+impl XmrgData {
+    // applies op to each pair of cells, MISSING where either is missing or the result is not finite
+    pub fn combine<F>(&self, other: &XmrgData, op: F) -> io::Result<XmrgData>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        self.header.check_factor(&other.header)?;
+        let header = self.header.intersection(&other.header).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "grid windows {:?} and {:?} do not overlap",
+                    self.header, other.header
+                ),
+            )
+        })?;
+
+        let values = (0..header.rows() as usize)
+            .map(|row| {
+                (0..header.columns() as usize)
+                    .map(|column| {
+                        let hrap = header.hrap_point(column, row);
+                        match (self.value_at(hrap), other.value_at(hrap)) {
+                            (Some(a), Some(b)) => finite_or_missing(op(a, b)),
+                            _ => MISSING,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(XmrgData::new(header, None, values))
+    }
+
+    pub fn add(&self, other: &XmrgData) -> io::Result<XmrgData> {
+        self.combine(other, |a, b| a + b)
+    }
+
+    pub fn subtract(&self, other: &XmrgData) -> io::Result<XmrgData> {
+        self.combine(other, |a, b| a - b)
+    }
+
+    pub fn multiply(&self, other: &XmrgData) -> io::Result<XmrgData> {
+        self.combine(other, |a, b| a * b)
+    }
+
+    // division by zero is MISSING
+    pub fn divide(&self, other: &XmrgData) -> io::Result<XmrgData> {
+        self.combine(other, |a, b| a / b)
+    }
+
+    pub fn minimum(&self, other: &XmrgData) -> io::Result<XmrgData> {
+        self.combine(other, f64::min)
+    }
+
+    pub fn maximum(&self, other: &XmrgData) -> io::Result<XmrgData> {
+        self.combine(other, f64::max)
+    }
+
+    // applies op to every cell with data, missing cells stay missing
+    pub fn map<F>(&self, op: F) -> XmrgData
+    where
+        F: Fn(f64) -> f64,
+    {
+        let values = self
+            .values
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| {
+                        if is_missing(*value) {
+                            MISSING
+                        } else {
+                            finite_or_missing(op(*value))
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        XmrgData::new(self.header, None, values)
+    }
+
+    pub fn add_scalar(&self, scalar: f64) -> XmrgData {
+        self.map(|value| value + scalar)
+    }
+
+    pub fn multiply_scalar(&self, scalar: f64) -> XmrgData {
+        self.map(|value| value * scalar)
+    }
+
+    pub fn clamp(&self, min: f64, max: f64) -> XmrgData {
+        self.map(|value| value.max(min).min(max))
+    }
+
+    // values below threshold become 0, e.g. to remove light noise
+    pub fn threshold(&self, threshold: f64) -> XmrgData {
+        self.map(|value| if value < threshold { 0.0 } else { value })
+    }
+
+    // the value at absolute HRAP cell coordinates, None when missing or outside of the window
+    pub(crate) fn value_at(&self, hrap: Point) -> Option<f64> {
+        let position = self.header.grid_position(hrap);
+        if position.x < 0.0 || position.y < 0.0 {
+            return None;
+        }
+        self.values
+            .get(position.y as usize)
+            .and_then(|row| row.get(position.x as usize))
+            .copied()
+            .filter(|value| !is_missing(*value))
+    }
+}
+
+fn is_missing(value: f64) -> bool {
+    value == MISSING
+}
+
+fn finite_or_missing(value: f64) -> f64 {
+    if value.is_finite() {
+        value
+    } else {
+        MISSING
+    }
+}
+
+// a grid with the same value in every cell of a window, e.g. a uniform bias factor
+pub fn constant_grid(header: Header, value: f64) -> XmrgData {
+    XmrgData::new(
+        header,
+        None,
+        vec![vec![value; header.columns() as usize]; header.rows() as usize],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combine_test() {
+        let a = XmrgData::new(
+            Header::new(10, 20, 2, 2),
+            None,
+            vec![vec![1.0, 2.0], vec![3.0, MISSING]],
+        );
+        // overlaps the right column of a
+        let b = XmrgData::new(
+            Header::new(11, 20, 2, 2),
+            None,
+            vec![vec![4.0, 5.0], vec![0.0, 7.0]],
+        );
+
+        let difference = a.subtract(&b).unwrap();
+        assert_eq!(difference.header, Header::new(11, 20, 1, 2));
+        assert_eq!(difference.values, vec![vec![-2.0], vec![MISSING]]);
+
+        // negative differences are kept as data
+        let shifted = difference.add_scalar(1.0);
+        assert_eq!(shifted.values, vec![vec![-1.0], vec![MISSING]]);
+
+        let ratio = constant_grid(b.header, 2.0).divide(&b).unwrap();
+        assert_eq!(ratio.values[1], vec![MISSING, 2.0 / 7.0]);
+
+        assert_eq!(b.threshold(4.5).values[0], vec![0.0, 5.0]);
+        assert_eq!(b.clamp(1.0, 5.0).values[1], vec![1.0, 5.0]);
+        assert_eq!(a.maximum(&a).unwrap().values, a.values);
+
+        let apart = constant_grid(Header::new(50, 50, 1, 1), 1.0);
+        assert!(a.add(&apart).is_err());
+    }
+}
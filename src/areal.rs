@@ -0,0 +1,284 @@
+use crate::basin::Basin;
+use crate::datetime::DateSegments;
+use crate::geo::Point;
+use crate::headers::{CellPosition, Header};
+use crate::hrap::{hrap_to_latlon_with, LonConvention};
+use crate::mask::BasinMask;
+use crate::stack::GridStack;
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::io;
+
+// mean areal precipitation (MAP). Weights are the fraction of each HRAP cell inside a basin, found by testing
+// a samples x samples lattice of points across the cell, so they only need to be built once per header
+
+const DEFAULT_SAMPLES: usize = 10;
+const BOUNDS_MARGIN: f64 = 0.1; // degrees, comfortably more than half an HRAP cell anywhere in the grid
+
+#[derive(Debug, Copy, Clone)]
+pub struct CellWeight {
+    pub row: usize,
+    pub column: usize,
+    pub weight: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BasinWeights {
+    pub id: String,
+    pub cells: Vec<CellWeight>,
+}
+
+impl BasinWeights {
+    // the weighted mean of the cells with data and the fraction of the basin's weight they cover
+    pub fn average(&self, values: &[Vec<f64>]) -> (f64, f64) {
+        let total: f64 = self.cells.iter().map(|c| c.weight).sum();
+        let (sum, covered) = self
+            .cells
+            .iter()
+            .map(|c| (values[c.row][c.column], c.weight))
+            .filter(|(value, _)| *value >= 0.0)
+            .fold((0.0, 0.0), |(sum, covered), (value, weight)| {
+                (sum + value * weight, covered + weight)
+            });
+
+        if covered > 0.0 {
+            (sum / covered, covered / total)
+        } else {
+            (MISSING, 0.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BasinAverage {
+    pub id: String,
+    pub value: f64,
+    pub coverage: f64, // fraction of the basin with data
+}
+
+impl BasinAverage {
+    pub fn csv_row(&self) -> String {
+        format!("{},{},{}", self.id, self.value, self.coverage)
+    }
+}
+
+// a MAP value for every step from start, each valid at the end of its step
+#[derive(Debug, Clone)]
+pub struct MapSeries {
+    pub id: String,
+    pub start: DateSegments,
+    pub step_hours: i64,
+    pub values: Vec<f64>,
+}
+
+impl MapSeries {
+    pub fn time(&self, index: usize) -> DateSegments {
+        self.start.add_hours(self.step_hours * index as i64)
+    }
+}
+
+pub struct WeightTable {
+    pub header: Header,
+    pub basins: Vec<BasinWeights>,
+}
+
+impl WeightTable {
+    pub fn new(header: Header, basins: &[Basin]) -> Self {
+        Self::with_samples(header, basins, DEFAULT_SAMPLES)
+    }
+
+    pub fn with_samples(header: Header, basins: &[Basin], samples: usize) -> Self {
+        let centres: Vec<Vec<Point>> = (0..header.rows() as usize)
+            .map(|row| {
+                (0..header.columns() as usize)
+                    .map(|column| cell_latlon(header.hrap_point(column, row)))
+                    .collect()
+            })
+            .collect();
+
+        let basins = basins
+            .iter()
+            .map(|basin| BasinWeights {
+                id: basin.id.clone(),
+                cells: basin_cells(header, &centres, basin, samples.max(1)),
+            })
+            .collect();
+
+        WeightTable { header, basins }
+    }
+
+    pub fn from_masks(header: Header, masks: &[BasinMask]) -> Self {
+        WeightTable {
+            header,
+            basins: masks.iter().map(|mask| mask.weights()).collect(),
+        }
+    }
+
+    pub fn map(&self, data: &XmrgData) -> io::Result<Vec<BasinAverage>> {
+        self.map_values(data.header, &data.values)
+    }
+
+    pub fn map_values(&self, header: Header, values: &[Vec<f64>]) -> io::Result<Vec<BasinAverage>> {
+        if header != self.header {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "grid header {:?} does not match the weight table header {:?}",
+                    header, self.header
+                ),
+            ));
+        }
+
+        Ok(self
+            .basins
+            .iter()
+            .map(|basin| {
+                let (value, coverage) = basin.average(values);
+                BasinAverage {
+                    id: basin.id.clone(),
+                    value,
+                    coverage,
+                }
+            })
+            .collect())
+    }
+
+    // hourly MAP series for every basin, missing hours of the stack are MISSING
+    pub fn map_stack(&self, stack: &GridStack) -> io::Result<Vec<MapSeries>> {
+        let averages = stack
+            .values
+            .iter()
+            .map(|values| self.map_values(stack.header, values))
+            .collect::<io::Result<Vec<Vec<BasinAverage>>>>()?;
+
+        Ok(self.series(stack.times.first().copied(), 1, &averages))
+    }
+
+    // MAP series from consecutive grids step_hours apart, e.g. the totals of an Accumulation
+    pub fn map_grids(&self, grids: &[XmrgData], step_hours: i64) -> io::Result<Vec<MapSeries>> {
+        let start = grids.first().and_then(|g| g.valid_time());
+        let expected = |i: usize| start.map(|s| s.add_hours(step_hours * i as i64));
+
+        if let Some(i) = (0..grids.len()).find(|i| grids[*i].valid_time() != expected(*i)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "grid {} is not valid {} hours after the one before it",
+                    i, step_hours
+                ),
+            ));
+        }
+
+        let averages = grids
+            .iter()
+            .map(|grid| self.map(grid))
+            .collect::<io::Result<Vec<Vec<BasinAverage>>>>()?;
+
+        Ok(self.series(start, step_hours, &averages))
+    }
+
+    fn series(
+        &self,
+        start: Option<DateSegments>,
+        step_hours: i64,
+        averages: &[Vec<BasinAverage>],
+    ) -> Vec<MapSeries> {
+        let start = match start {
+            Some(start) => start,
+            None => return Vec::new(),
+        };
+
+        self.basins
+            .iter()
+            .enumerate()
+            .map(|(i, basin)| MapSeries {
+                id: basin.id.clone(),
+                start,
+                step_hours,
+                values: averages.iter().map(|step| step[i].value).collect(),
+            })
+            .collect()
+    }
+}
+
+fn cell_latlon(hrap: Point) -> Point {
+    hrap_to_latlon_with(hrap.x, hrap.y, LonConvention::Signed)
+}
+
+fn basin_cells(
+    header: Header,
+    centres: &[Vec<Point>],
+    basin: &Basin,
+    samples: usize,
+) -> Vec<CellWeight> {
+    let (min, max) = basin.bounds();
+    let near = |p: &Point| {
+        p.x >= min.x - BOUNDS_MARGIN
+            && p.x <= max.x + BOUNDS_MARGIN
+            && p.y >= min.y - BOUNDS_MARGIN
+            && p.y <= max.y + BOUNDS_MARGIN
+    };
+    let step = 1.0 / samples as f64;
+
+    let mut cells = Vec::new();
+    for (row, row_centres) in centres.iter().enumerate() {
+        for (column, _) in row_centres.iter().enumerate().filter(|(_, p)| near(p)) {
+            let corner = header.cell_point(column, row, CellPosition::LowerLeft);
+            let size = header.cell_size();
+            let inside = (0..samples * samples)
+                .filter(|i| {
+                    let x = corner.x + (((i % samples) as f64) + 0.5) * step * size;
+                    let y = corner.y + (((i / samples) as f64) + 0.5) * step * size;
+                    basin.contains(cell_latlon(Point::new(x, y)))
+                })
+                .count();
+
+            if inside > 0 {
+                cells.push(CellWeight {
+                    row,
+                    column,
+                    weight: inside as f64 / (samples * samples) as f64,
+                });
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::Polygon;
+
+    #[test]
+    fn map_test() {
+        let header = Header::new(367, 263, 4, 4);
+
+        // a box between the centres of the middle four cells of the bottom two rows, a quarter of each
+        let corners = [
+            header.hrap_point(1, 0),
+            header.hrap_point(2, 0),
+            header.hrap_point(2, 1),
+            header.hrap_point(1, 1),
+        ];
+        let ring = corners.iter().map(|p| cell_latlon(*p)).collect();
+        let basin = Basin::new(String::from("B1"), vec![Polygon::new(ring, vec![])]);
+
+        let table = WeightTable::new(header, &[basin]);
+        let total: f64 = table.basins[0].cells.iter().map(|c| c.weight).sum();
+        assert!((total - 1.0).abs() < 0.05);
+
+        let mut values = vec![vec![0.0; 4]; 4];
+        for row in values.iter_mut() {
+            row[1] = 2.0;
+            row[2] = 4.0;
+        }
+        let data = XmrgData::new(header, None, values);
+
+        let map = table.map(&data).unwrap();
+        assert_eq!(map[0].id, "B1");
+        assert!((map[0].value - 3.0).abs() < 0.01);
+        assert!((map[0].coverage - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::geo::{Point, Polygon};
+use crate::json::{parse_json, Json};
+
+use std::fs;
+use std::io;
+
+// basin boundaries in lat/lon with conventional signed longitudes (negative west), as GeoJSON and WKT use
+#[derive(Debug, Clone)]
+pub struct Basin {
+    pub id: String,
+    pub polygons: Vec<Polygon>,
+}
+
+impl Basin {
+    pub fn new(id: String, polygons: Vec<Polygon>) -> Self {
+        Basin { id, polygons }
+    }
+
+    // POLYGON ((lon lat, ...), (hole...)) or MULTIPOLYGON (((lon lat, ...)), ...)
+    pub fn from_wkt(id: &str, wkt: &str) -> io::Result<Self> {
+        let text = wkt.trim();
+        let upper = text.to_uppercase();
+        let (multi, rest) = if upper.starts_with("MULTIPOLYGON") {
+            (true, &text["MULTIPOLYGON".len()..])
+        } else if upper.starts_with("POLYGON") {
+            (false, &text["POLYGON".len()..])
+        } else {
+            return Err(invalid("only POLYGON and MULTIPOLYGON wkt is supported"));
+        };
+
+        let mut parser = WktParser {
+            chars: rest.chars().collect(),
+            pos: 0,
+        };
+        let nested = parser.nested()?;
+
+        let polygons = if multi {
+            nested
+                .into_lists()?
+                .into_iter()
+                .map(polygon_from_rings)
+                .collect::<io::Result<Vec<Polygon>>>()?
+        } else {
+            vec![polygon_from_rings(nested)?]
+        };
+
+        Ok(Basin::new(String::from(id), polygons))
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        self.polygons.iter().any(|p| p.contains(point))
+    }
+
+    pub fn bounds(&self) -> (Point, Point) {
+        self.polygons.iter().map(|p| p.bounds()).fold(
+            (
+                Point::new(f64::MAX, f64::MAX),
+                Point::new(f64::MIN, f64::MIN),
+            ),
+            |(min, max), (p_min, p_max)| {
+                (
+                    Point::new(min.x.min(p_min.x), min.y.min(p_min.y)),
+                    Point::new(max.x.max(p_max.x), max.y.max(p_max.y)),
+                )
+            },
+        )
+    }
+}
+
+pub fn read_geojson(path: &str) -> io::Result<Vec<Basin>> {
+    parse_geojson(&fs::read_to_string(path)?)
+}
+
+// a FeatureCollection, a single Feature or a bare geometry. Basins are named by the feature id or the
+// "id" or "name" property, falling back to the position of the feature in the file
+pub fn parse_geojson(text: &str) -> io::Result<Vec<Basin>> {
+    let json = parse_json(text)?;
+
+    let features: Vec<&Json> = match json.get("type").and_then(|t| t.as_str()) {
+        Some("FeatureCollection") => json
+            .get("features")
+            .and_then(|f| f.as_array())
+            .ok_or_else(|| invalid("FeatureCollection has no features"))?
+            .iter()
+            .collect(),
+        _ => vec![&json],
+    };
+
+    features
+        .iter()
+        .enumerate()
+        .map(|(i, feature)| {
+            let geometry = feature.get("geometry").unwrap_or(feature);
+            Ok(Basin::new(
+                feature_id(feature, i),
+                geojson_polygons(geometry)?,
+            ))
+        })
+        .collect()
+}
+
+fn feature_id(feature: &Json, index: usize) -> String {
+    let properties = feature.get("properties");
+    let id = feature
+        .get("id")
+        .or_else(|| properties.and_then(|p| p.get("id")))
+        .or_else(|| properties.and_then(|p| p.get("name")));
+
+    match id {
+        Some(Json::String(s)) => s.clone(),
+        Some(Json::Number(n)) => n.to_string(),
+        _ => index.to_string(),
+    }
+}
+
+fn geojson_polygons(geometry: &Json) -> io::Result<Vec<Polygon>> {
+    let coordinates = geometry
+        .get("coordinates")
+        .and_then(|c| c.as_array())
+        .ok_or_else(|| invalid("geometry has no coordinates"))?;
+
+    match geometry.get("type").and_then(|t| t.as_str()) {
+        Some("Polygon") => Ok(vec![geojson_polygon(coordinates)?]),
+        Some("MultiPolygon") => coordinates
+            .iter()
+            .map(|p| geojson_polygon(p.as_array().unwrap_or_default()))
+            .collect(),
+        other => Err(invalid(&format!(
+            "unsupported geometry type {:?}",
+            other.unwrap_or("none")
+        ))),
+    }
+}
+
+fn geojson_polygon(rings: &[Json]) -> io::Result<Polygon> {
+    let mut rings = rings
+        .iter()
+        .map(|ring| {
+            ring.as_array()
+                .unwrap_or_default()
+                .iter()
+                .map(|position| {
+                    let xy = position.as_array().unwrap_or_default();
+                    match (
+                        xy.first().and_then(|x| x.as_f64()),
+                        xy.get(1).and_then(|y| y.as_f64()),
+                    ) {
+                        (Some(x), Some(y)) => Ok(Point::new(x, y)),
+                        _ => Err(invalid("position is not a pair of numbers")),
+                    }
+                })
+                .collect::<io::Result<Vec<Point>>>()
+        })
+        .collect::<io::Result<Vec<Vec<Point>>>>()?;
+
+    if rings.is_empty() {
+        return Err(invalid("polygon has no rings"));
+    }
+    let exterior = rings.remove(0);
+    Ok(Polygon::new(exterior, rings))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, String::from(message))
+}
+
+enum Nested {
+    Coordinates(Vec<Point>),
+    Lists(Vec<Nested>),
+}
+
+impl Nested {
+    fn into_lists(self) -> io::Result<Vec<Nested>> {
+        match self {
+            Nested::Lists(lists) => Ok(lists),
+            Nested::Coordinates(_) => Err(invalid("expected a nested list in wkt")),
+        }
+    }
+
+    fn into_coordinates(self) -> io::Result<Vec<Point>> {
+        match self {
+            Nested::Coordinates(points) => Ok(points),
+            Nested::Lists(_) => Err(invalid("expected a coordinate list in wkt")),
+        }
+    }
+}
+
+fn polygon_from_rings(nested: Nested) -> io::Result<Polygon> {
+    let mut rings = nested
+        .into_lists()?
+        .into_iter()
+        .map(|ring| ring.into_coordinates())
+        .collect::<io::Result<Vec<Vec<Point>>>>()?;
+
+    if rings.is_empty() {
+        return Err(invalid("polygon has no rings"));
+    }
+    let exterior = rings.remove(0);
+    Ok(Polygon::new(exterior, rings))
+}
+
+struct WktParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl WktParser {
+    fn peek(&mut self) -> Option<char> {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: char) -> io::Result<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(invalid(&format!("expected '{}' in wkt", c)))
+        }
+    }
+
+    // ( ... ) holding either more ( ... ) or a comma separated list of "x y"
+    fn nested(&mut self) -> io::Result<Nested> {
+        self.expect('(')?;
+        if self.peek() == Some('(') {
+            let mut lists = vec![self.nested()?];
+            while self.peek() == Some(',') {
+                self.pos += 1;
+                lists.push(self.nested()?);
+            }
+            self.expect(')')?;
+            return Ok(Nested::Lists(lists));
+        }
+
+        let start = self.pos;
+        while self.pos < self.chars.len() && self.chars[self.pos] != ')' {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        self.expect(')')?;
+
+        text.split(',')
+            .map(|pair| {
+                let numbers = pair
+                    .split_whitespace()
+                    .map(|n| n.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| invalid("invalid coordinate in wkt"))?;
+                match numbers[..] {
+                    [x, y, ..] => Ok(Point::new(x, y)),
+                    _ => Err(invalid("coordinate needs an x and y in wkt")),
+                }
+            })
+            .collect::<io::Result<Vec<Point>>>()
+            .map(Nested::Coordinates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_wkt_test() {
+        let basin = Basin::from_wkt(
+            "ABC",
+            "MULTIPOLYGON (((-100 30, -99 30, -99 31, -100 31, -100 30)), ((-98 30, -97 30, -97 31)))",
+        )
+        .unwrap();
+
+        assert_eq!(basin.polygons.len(), 2);
+        assert!(basin.contains(Point::new(-99.5, 30.5)));
+        assert!(!basin.contains(Point::new(-98.5, 30.5)));
+
+        let holed =
+            Basin::from_wkt("D", "POLYGON((0 0, 4 0, 4 4, 0 4), (1 1, 2 1, 2 2, 1 2))").unwrap();
+        assert_eq!(holed.polygons[0].holes.len(), 1);
+    }
+
+    #[test]
+    fn parse_geojson_test() {
+        let text = r#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {"name": "BASIN1"},
+                "geometry": {"type": "Polygon", "coordinates": [[[-100, 30], [-99, 30], [-99, 31], [-100, 30]]]}
+            }]
+        }"#;
+
+        let basins = parse_geojson(text).unwrap();
+
+        assert_eq!(basins[0].id, "BASIN1");
+        assert_eq!(basins[0].polygons[0].exterior[2], Point::new(-99.0, 31.0));
+    }
+}
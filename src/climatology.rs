@@ -0,0 +1,311 @@
+use crate::accumulate::MissingPolicy;
+use crate::datetime::{days_in_month, is_leap_year, DateSegments};
+use crate::headers::Header;
+use crate::reader::XmrgReader;
+use crate::stack::GridStack;
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::io::prelude::*;
+
+// per cell running statistics over some set of hourly grids, indexed rows x columns like XmrgData
+pub struct CellStats {
+    pub sum: Vec<Vec<f64>>,
+    pub count: Vec<Vec<u32>>, // hours with data
+    pub wet_hours: Vec<Vec<u32>>,
+    pub max: Vec<Vec<f64>>,
+}
+
+impl CellStats {
+    pub fn new(header: Header) -> Self {
+        let rows = header.rows() as usize;
+        let columns = header.columns() as usize;
+        CellStats {
+            sum: vec![vec![0.0; columns]; rows],
+            count: vec![vec![0; columns]; rows],
+            wet_hours: vec![vec![0; columns]; rows],
+            max: vec![vec![MISSING; columns]; rows],
+        }
+    }
+
+    fn fold_row(&mut self, row: usize, values: &[f64], wet_threshold: f64) {
+        for (column, value) in values.iter().enumerate().filter(|(_, v)| **v >= 0.0) {
+            self.sum[row][column] += value;
+            self.count[row][column] += 1;
+            if *value >= wet_threshold {
+                self.wet_hours[row][column] += 1;
+            }
+            self.max[row][column] = self.max[row][column].max(*value);
+        }
+    }
+
+    // mean precipitation per hour with data, MISSING where no hour had data
+    pub fn hourly_mean(&self) -> Vec<Vec<f64>> {
+        self.sum
+            .iter()
+            .zip(self.count.iter())
+            .map(|(sums, counts)| {
+                sums.iter()
+                    .zip(counts.iter())
+                    .map(|(sum, count)| match count {
+                        0 => MISSING,
+                        n => sum / f64::from(*n),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// folds hourly grids into monthly and annual statistics in one pass.
+// a grid valid at H holds the hour ending at H, so the grid valid at 00Z on the 1st counts toward the previous month.
+// the policy decides which months and years of a cell are complete enough to count toward the long term means
+pub struct Climatology {
+    header: Header,
+    wet_threshold: f64,
+    policy: MissingPolicy,
+    monthly: BTreeMap<(i32, i32), CellStats>,
+    annual: BTreeMap<i32, CellStats>,
+}
+
+impl Climatology {
+    // hours with at least wet_threshold mm are counted as wet
+    pub fn new(header: Header, wet_threshold: f64, policy: MissingPolicy) -> Self {
+        Climatology {
+            header,
+            wet_threshold,
+            policy,
+            monthly: BTreeMap::new(),
+            annual: BTreeMap::new(),
+        }
+    }
+
+    pub fn fold(&mut self, time: DateSegments, data: &XmrgData) -> io::Result<()> {
+        self.check_header(data.header)?;
+
+        for (row, values) in data.values.iter().enumerate() {
+            self.fold_row(time, row, values);
+        }
+
+        Ok(())
+    }
+
+    // folds a grid row by row without holding the whole grid in memory
+    pub fn fold_reader<R: Read>(
+        &mut self,
+        time: DateSegments,
+        reader: XmrgReader<R>,
+    ) -> io::Result<()> {
+        self.check_header(reader.header())?;
+
+        for (row, result) in reader.enumerate() {
+            self.fold_row(time, row, &result?.values);
+        }
+
+        Ok(())
+    }
+
+    pub fn fold_stack(&mut self, stack: &GridStack) -> io::Result<()> {
+        self.check_header(stack.header)?;
+
+        for (time, grid) in stack.times.iter().zip(stack.values.iter()) {
+            for (row, values) in grid.iter().enumerate() {
+                self.fold_row(*time, row, values);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn monthly(&self, year: i32, month: i32) -> Option<&CellStats> {
+        self.monthly.get(&(year, month))
+    }
+
+    pub fn annual(&self, year: i32) -> Option<&CellStats> {
+        self.annual.get(&year)
+    }
+
+    pub fn months(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.monthly.keys().copied()
+    }
+
+    pub fn years(&self) -> impl Iterator<Item = i32> + '_ {
+        self.annual.keys().copied()
+    }
+
+    // the mean total for a calendar month across every year with data in that month
+    pub fn monthly_mean(&self, month: i32) -> io::Result<Vec<Vec<f64>>> {
+        self.mean_total(self.monthly.iter().filter(|((_, m), _)| *m == month).map(
+            |((year, month), stats)| {
+                let hours = i64::from(days_in_month(*year, *month)) * 24;
+                (format!("{}-{:02}", year, month), hours, stats)
+            },
+        ))
+    }
+
+    pub fn annual_mean(&self) -> io::Result<Vec<Vec<f64>>> {
+        self.mean_total(self.annual.iter().map(|(year, stats)| {
+            let hours = if is_leap_year(*year) { 366 } else { 365 } * 24;
+            (year.to_string(), hours, stats)
+        }))
+    }
+
+    // the mean of the period totals of each cell, over the periods the policy accepts. Each period comes with
+    // a name for errors and the number of hours in it
+    fn mean_total<'a>(
+        &self,
+        periods: impl Iterator<Item = (String, i64, &'a CellStats)>,
+    ) -> io::Result<Vec<Vec<f64>>> {
+        let rows = self.header.rows() as usize;
+        let columns = self.header.columns() as usize;
+        let mut sum = vec![vec![0.0; columns]; rows];
+        let mut count = vec![vec![0u32; columns]; rows];
+
+        for (name, hours, stats) in periods {
+            for row in 0..rows {
+                for column in 0..columns {
+                    let present = i64::from(stats.count[row][column]);
+                    let accepted = match self.policy {
+                        MissingPolicy::Fail if present < hours => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "{} of {} hours are missing at row {}, column {} in {}",
+                                    hours - present,
+                                    hours,
+                                    row,
+                                    column,
+                                    name
+                                ),
+                            ))
+                        }
+                        MissingPolicy::Fail => true,
+                        MissingPolicy::Skip => present > 0,
+                        MissingPolicy::MinFraction(fraction) => {
+                            present > 0 && present as f64 / hours as f64 >= fraction
+                        }
+                    };
+                    if accepted {
+                        sum[row][column] += stats.sum[row][column];
+                        count[row][column] += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(sum
+            .iter()
+            .zip(count.iter())
+            .map(|(sums, counts)| {
+                sums.iter()
+                    .zip(counts.iter())
+                    .map(|(sum, count)| match count {
+                        0 => MISSING,
+                        n => sum / f64::from(*n),
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    fn check_header(&self, header: Header) -> io::Result<()> {
+        if header == self.header {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "grid header {:?} does not match the climatology header {:?}",
+                    header, self.header
+                ),
+            ))
+        }
+    }
+
+    fn fold_row(&mut self, time: DateSegments, row: usize, values: &[f64]) {
+        let period = time.add_hours(-1);
+        let header = self.header;
+
+        self.monthly
+            .entry((period.year, period.month))
+            .or_insert_with(|| CellStats::new(header))
+            .fold_row(row, values, self.wet_threshold);
+
+        self.annual
+            .entry(period.year)
+            .or_insert_with(|| CellStats::new(header))
+            .fold_row(row, values, self.wet_threshold);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_monthly_and_annual_test() {
+        let header = Header::new(10, 20, 2, 1);
+        let mut climatology = Climatology::new(header, 0.25, MissingPolicy::Skip);
+
+        let grids = [
+            (DateSegments::new(2019, 1, 15, 6), vec![vec![1.0, MISSING]]),
+            (DateSegments::new(2019, 2, 1, 0), vec![vec![3.0, 0.1]]),
+            (DateSegments::new(2020, 1, 10, 6), vec![vec![5.0, 0.5]]),
+        ];
+        for (time, values) in grids.iter() {
+            let data = XmrgData::new(header, None, values.clone());
+            climatology.fold(*time, &data).unwrap();
+        }
+
+        // 00Z on February 1st closes the last hour of January
+        let jan_2019 = climatology.monthly(2019, 1).unwrap();
+        assert_eq!(jan_2019.sum[0], vec![4.0, 0.1]);
+        assert_eq!(jan_2019.count[0], vec![2, 1]);
+        assert_eq!(jan_2019.wet_hours[0], vec![2, 0]);
+        assert_eq!(jan_2019.max[0], vec![3.0, 0.1]);
+        assert!(climatology.monthly(2019, 2).is_none());
+
+        assert_eq!(climatology.monthly_mean(1).unwrap()[0], vec![4.5, 0.3]);
+        assert_eq!(climatology.annual(2020).unwrap().wet_hours[0], vec![1, 1]);
+        assert_eq!(climatology.years().collect::<Vec<i32>>(), vec![2019, 2020]);
+    }
+
+    #[test]
+    fn partly_missing_month_test() {
+        // every hour of January 2019 and 2020, the second cell only has 3 hours in 2019
+        let header = Header::new(10, 20, 2, 1);
+        let fold = |policy| {
+            let mut climatology = Climatology::new(header, 0.25, policy);
+            for year in [2019, 2020] {
+                let start = DateSegments::new(year, 1, 1, 1);
+                for hour in 0..744 {
+                    let second = if year == 2020 || hour < 3 {
+                        0.5
+                    } else {
+                        MISSING
+                    };
+                    let data = XmrgData::new(header, None, vec![vec![0.5, second]]);
+                    climatology.fold(start.add_hours(hour), &data).unwrap();
+                }
+            }
+            climatology
+        };
+
+        // a 1.5 mm 2019 "total" would pull the mean down to 186.75
+        let climatology = fold(MissingPolicy::MinFraction(0.9));
+        assert_eq!(climatology.monthly_mean(1).unwrap()[0], vec![372.0, 372.0]);
+        // January alone is far short of a year
+        assert_eq!(
+            climatology.annual_mean().unwrap()[0],
+            vec![MISSING, MISSING]
+        );
+
+        let climatology = fold(MissingPolicy::Skip);
+        assert_eq!(climatology.monthly_mean(1).unwrap()[0], vec![372.0, 186.75]);
+
+        let climatology = fold(MissingPolicy::Fail);
+        assert!(climatology.monthly_mean(1).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use crate::geo::Point;
+use crate::headers::{Header, Metadata};
+use crate::hrap::{latlon_to_hrap_with, LonConvention};
+use crate::XmrgData;
+
+use std::io;
+
+// points along each edge of a lat/lon box, the box is curved in HRAP space
+const EDGE_SAMPLES: usize = 20;
+
+impl XmrgData {
+    // the cells from (x_min, y_min) to (x_max, y_max) inclusive, in the units of the grid like xor and yor,
+    // limited to the window of the grid
+    pub fn crop(&self, x_min: i32, y_min: i32, x_max: i32, y_max: i32) -> io::Result<XmrgData> {
+        let requested = Header::new(x_min, y_min, x_max - x_min + 1, y_max - y_min + 1)
+            .with_factor_of(&self.header);
+        let header = match self.header.intersection(&requested) {
+            Some(header) if x_max >= x_min && y_max >= y_min => header,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "HRAP range ({}, {}) to ({}, {}) is outside of the grid {:?}",
+                        x_min, y_min, x_max, y_max, self.header
+                    ),
+                ))
+            }
+        };
+
+        let column = (header.xor() - self.header.xor()) as usize;
+        let row = (header.yor() - self.header.yor()) as usize;
+        let values: Vec<Vec<f64>> = self.values[row..row + header.rows() as usize]
+            .iter()
+            .map(|r| r[column..column + header.columns() as usize].to_vec())
+            .collect();
+
+        let metadata = self.valid_time().map(|time| {
+            let flag = self
+                .metadata
+                .as_ref()
+                .and_then(|m| m.process_flag())
+                .unwrap_or_else(|| String::from("CROP"));
+            Metadata::for_product(time, flag.trim(), &values)
+        });
+
+        Ok(XmrgData::new(header, metadata, values))
+    }
+
+    // every cell with any part inside of the box, longitudes signed (negative west)
+    pub fn crop_latlon(
+        &self,
+        west: f64,
+        south: f64,
+        east: f64,
+        north: f64,
+    ) -> io::Result<XmrgData> {
+        let edge = |from: Point, to: Point| {
+            (0..=EDGE_SAMPLES).map(move |i| {
+                let t = i as f64 / EDGE_SAMPLES as f64;
+                let lon = from.x + (to.x - from.x) * t;
+                let lat = from.y + (to.y - from.y) * t;
+                latlon_to_hrap_with(lon, lat, LonConvention::Signed)
+            })
+        };
+
+        let sw = Point::new(west, south);
+        let se = Point::new(east, south);
+        let ne = Point::new(east, north);
+        let nw = Point::new(west, north);
+        let (min, max) = edge(sw, se)
+            .chain(edge(se, ne))
+            .chain(edge(ne, nw))
+            .chain(edge(nw, sw))
+            .fold(
+                (
+                    Point::new(f64::MAX, f64::MAX),
+                    Point::new(f64::MIN, f64::MIN),
+                ),
+                |(min, max), p| {
+                    (
+                        Point::new(min.x.min(p.x), min.y.min(p.y)),
+                        Point::new(max.x.max(p.x), max.y.max(p.y)),
+                    )
+                },
+            );
+
+        // cell corners are on integer coordinates in the units of the grid
+        let factor = f64::from(self.header.factor());
+        self.crop(
+            (min.x * factor).floor() as i32,
+            (min.y * factor).floor() as i32,
+            (max.x * factor).floor() as i32,
+            (max.y * factor).floor() as i32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hrap::{hrap_to_latlon, signed_longitude};
+
+    #[test]
+    fn crop_test() {
+        let header = Header::new(100, 200, 4, 3);
+        let values = (0..3)
+            .map(|row| (0..4).map(|column| f64::from(row * 10 + column)).collect())
+            .collect();
+        let data = XmrgData::new(header, None, values);
+
+        let cropped = data.crop(101, 201, 110, 201).unwrap();
+        assert_eq!(cropped.header, Header::new(101, 201, 3, 1));
+        assert_eq!(cropped.values, vec![vec![11.0, 12.0, 13.0]]);
+        assert!(data.crop(0, 0, 10, 10).is_err());
+
+        // a small box around the centre of cell (102, 200)
+        let centre = hrap_to_latlon(102.5, 200.5);
+        let (lon, lat) = (signed_longitude(centre.x), centre.y);
+        let cropped = data
+            .crop_latlon(lon - 0.01, lat - 0.01, lon + 0.01, lat + 0.01)
+            .unwrap();
+        assert_eq!(cropped.header, Header::new(102, 200, 1, 1));
+        assert_eq!(cropped.values, vec![vec![2.0]]);
+    }
+}
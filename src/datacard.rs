@@ -0,0 +1,203 @@
+use crate::areal::MapSeries;
+use crate::datetime::{days_in_month, DateSegments};
+use crate::utils::MISSING;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+// NWSRFS datacard files, fixed column cards (1 based columns):
+// $ comment cards
+// header card 1: 1-4 data type, 15-18 dimension, 20-23 units, 25-26 time step in hours, 30-41 id, 50-69 description
+// header card 2: 1-2 first month, 5-8 first year, 10-11 last month, 15-18 last year, 20-21 values per card, 25-32 format
+// data cards: 1-12 id, 13-16 month and 2 digit year (MMYY), 18-20 card number within the month, 21-80 values
+// every month is complete, the first value of a month ends one time step after 00Z on the 1st. Hours with no
+// data and the parts of the first and last months outside of the series are written as -999.0
+
+const VALUES_PER_CARD: usize = 6;
+const VALUE_FORMAT: &str = "(6F10.3)";
+
+pub fn write_datacard(path: &str, series: &MapSeries, description: &str) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_datacard_to(&mut writer, series, description)?;
+    writer.flush()
+}
+
+// writes one file per series into dir, named <id>.MAP
+pub fn write_datacards(dir: &str, series: &[MapSeries]) -> io::Result<()> {
+    for s in series {
+        let path = format!("{}/{}.MAP", dir.trim_end_matches('/'), s.id);
+        write_datacard(&path, s, &s.id)?;
+    }
+    Ok(())
+}
+
+pub fn write_datacard_to<W: Write>(
+    writer: &mut W,
+    series: &MapSeries,
+    description: &str,
+) -> io::Result<()> {
+    if series.step_hours <= 0 || 24 % series.step_hours != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "datacard time steps must divide a day, not {} hours",
+                series.step_hours
+            ),
+        ));
+    }
+
+    if i64::from(series.start.hour) % series.step_hours != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "MAP series {} steps must end on multiples of {} hours from 00Z",
+                series.id, series.step_hours
+            ),
+        ));
+    }
+
+    if series.values.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("MAP series {} has no values", series.id),
+        ));
+    }
+
+    let first = month_of(series, 0);
+    let last = month_of(series, series.values.len() - 1);
+
+    writeln!(
+        writer,
+        "$ MAP time series for {} written by rexmrg",
+        series.id
+    )?;
+    writeln!(
+        writer,
+        "{}",
+        card(&[
+            (1, String::from("MAP")),
+            (15, String::from("L")),
+            (20, String::from("MM")),
+            (25, format!("{:>2}", series.step_hours)),
+            (30, truncate(&series.id, 12)),
+            (50, truncate(description, 20)),
+        ])
+    )?;
+    writeln!(
+        writer,
+        "{}",
+        card(&[
+            (1, format!("{:>2}", first.month)),
+            (5, format!("{:>4}", first.year)),
+            (10, format!("{:>2}", last.month)),
+            (15, format!("{:>4}", last.year)),
+            (20, format!("{:>2}", VALUES_PER_CARD)),
+            (25, String::from(VALUE_FORMAT)),
+        ])
+    )?;
+
+    let mut month = first;
+    while month <= last {
+        let values = month_values(series, month);
+        for (i, chunk) in values.chunks(VALUES_PER_CARD).enumerate() {
+            let values: String = chunk.iter().map(|v| format!("{:>10.3}", v)).collect();
+            writeln!(
+                writer,
+                "{}",
+                card(&[
+                    (1, truncate(&series.id, 12)),
+                    (13, format!("{:02}{:02}", month.month, month.year % 100)),
+                    (18, format!("{:>3}", i + 1)),
+                    (21, values),
+                ])
+            )?;
+        }
+        month = next_month(month);
+    }
+
+    Ok(())
+}
+
+// the month holding a value, from the start of its time step, as a DateSegments at 00Z on the 1st
+fn month_of(series: &MapSeries, index: usize) -> DateSegments {
+    let start = series.time(index).add_hours(-series.step_hours);
+    DateSegments::new(start.year, start.month, 1, 0)
+}
+
+fn next_month(month: DateSegments) -> DateSegments {
+    match month.month {
+        12 => DateSegments::new(month.year + 1, 1, 1, 0),
+        m => DateSegments::new(month.year, m + 1, 1, 0),
+    }
+}
+
+fn month_values(series: &MapSeries, month: DateSegments) -> Vec<f64> {
+    let steps = i64::from(days_in_month(month.year, month.month)) * 24 / series.step_hours;
+    let offset = (month.hours_since_epoch() + series.step_hours - series.start.hours_since_epoch())
+        .div_euclid(series.step_hours);
+
+    (0..steps)
+        .map(|step| {
+            let index = offset + step;
+            if index >= 0 && (index as usize) < series.values.len() {
+                series.values[index as usize]
+            } else {
+                MISSING
+            }
+        })
+        .map(|value| if value < 0.0 { MISSING } else { value })
+        .collect()
+}
+
+fn truncate(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}
+
+// places each field at its 1 based column, padding with spaces
+fn card(fields: &[(usize, String)]) -> String {
+    let mut line = String::new();
+    for (column, text) in fields {
+        while line.chars().count() < column - 1 {
+            line.push(' ');
+        }
+        line.push_str(text);
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_datacard_test() {
+        // four 6 hour values, the last ending at 00Z on March 1st
+        let series = MapSeries {
+            id: String::from("ABCT2"),
+            start: DateSegments::new(2020, 2, 29, 6),
+            step_hours: 6,
+            values: vec![1.0, 2.5, -999.0, 0.25],
+        };
+
+        let mut bytes = Vec::new();
+        write_datacard_to(&mut bytes, &series, "ABC BASIN").unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(
+            lines[1],
+            "MAP           L    MM    6   ABCT2               ABC BASIN"
+        );
+        assert_eq!(lines[2], " 2  2020  2   2020  6   (6F10.3)");
+
+        // 29 days of 4 values is 116 values on 20 cards, the series is the last 4
+        assert_eq!(lines.len(), 3 + 20);
+        assert_eq!(
+            lines[21],
+            "ABCT2       0220  19  -999.000  -999.000  -999.000  -999.000     1.000     2.500"
+        );
+        assert_eq!(lines[22], "ABCT2       0220  20  -999.000     0.250");
+    }
+}
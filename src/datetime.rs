@@ -0,0 +1,181 @@
+use std::fmt;
+
+// XMRG files are hourly, so date times are only resolved to the hour
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct DateSegments {
+    pub year: i32,
+    pub month: i32,
+    pub day: i32,
+    pub hour: i32, // in 24 hour time
+}
+
+impl DateSegments {
+    pub fn new(year: i32, month: i32, day: i32, hour: i32) -> Self {
+        DateSegments {
+            year,
+            month,
+            day,
+            hour,
+        }
+    }
+
+    // MMDDYYYYHH, as used in file names like xmrg0506199516z.gz
+    pub fn from_chars(chars: &str) -> Option<Self> {
+        Self::checked(
+            chars.get(4..8)?.parse().ok()?,
+            chars.get(0..2)?.parse().ok()?,
+            chars.get(2..4)?.parse().ok()?,
+            chars.get(8..10)?.parse().ok()?,
+        )
+    }
+
+    // YYYYMMDDHH, as used in file names like xmrg6_2020021600f006.gz
+    pub fn from_chars_ymdh(chars: &str) -> Option<Self> {
+        Self::checked(
+            chars.get(0..4)?.parse().ok()?,
+            chars.get(4..6)?.parse().ok()?,
+            chars.get(6..8)?.parse().ok()?,
+            chars.get(8..10)?.parse().ok()?,
+        )
+    }
+
+    // YYYY-MM-DD HH:MM:SS, as stored in the valid and saved datetime fields of the header
+    pub fn from_header_str(s: &str) -> Option<Self> {
+        let digits: String = s
+            .trim_matches(char::from(0))
+            .chars()
+            .filter(|c| c.is_ascii_digit())
+            .collect();
+
+        Self::from_chars_ymdh(&digits)
+    }
+
+    fn checked(year: i32, month: i32, day: i32, hour: i32) -> Option<Self> {
+        let valid = (1..=12).contains(&month)
+            && (1..=days_in_month(year, month)).contains(&day)
+            && (0..24).contains(&hour);
+
+        if valid {
+            Some(Self::new(year, month, day, hour))
+        } else {
+            None
+        }
+    }
+
+    // hours since 1970-01-01 00Z
+    pub fn hours_since_epoch(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * 24 + i64::from(self.hour)
+    }
+
+    pub fn from_hours(hours: i64) -> Self {
+        let (year, month, day) = civil_from_days(hours.div_euclid(24));
+        Self::new(year, month, day, hours.rem_euclid(24) as i32)
+    }
+
+    pub fn add_hours(&self, hours: i64) -> Self {
+        Self::from_hours(self.hours_since_epoch() + hours)
+    }
+
+    pub fn str_from_date(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02} {:02}:00:00",
+            self.year, self.month, self.day, self.hour
+        )
+    }
+}
+
+impl fmt::Display for DateSegments {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.str_from_date())
+    }
+}
+
+// finds the first run of 10 digits in a file name, either MMDDYYYYHH or YYYYMMDDHH
+pub fn read_xmrg_date(path: &str) -> Option<DateSegments> {
+    let name = path.rsplit(['/', '\\']).next()?;
+    let chars: Vec<char> = name.chars().collect();
+
+    chars
+        .split(|c| !c.is_ascii_digit())
+        .filter(|run| run.len() >= 10)
+        .find_map(|run| {
+            let digits: String = run[..10].iter().collect();
+            match &digits[..2] {
+                "19" | "20" => DateSegments::from_chars_ymdh(&digits),
+                _ => None,
+            }
+            .or_else(|| DateSegments::from_chars(&digits))
+        })
+}
+
+pub fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i32, month: i32) -> i32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i32, month: i32, day: i32) -> i64 {
+    let y = i64::from(if month <= 2 { year - 1 } else { year });
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = i64::from(month);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i32, i32, i32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year as i32, month as i32, day as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_file_name_date_test() {
+        let path = "xmrg0506199516z.gz";
+
+        let data_segments = read_xmrg_date(path).unwrap();
+
+        assert_eq!(data_segments, DateSegments::new(1995, 5, 6, 16));
+
+        let p2 = "archive/xmrg6_2020021600f006.gz";
+
+        let ds2 = read_xmrg_date(p2).unwrap();
+
+        assert_eq!(ds2, DateSegments::new(2020, 2, 16, 0));
+    }
+
+    #[test]
+    fn hours_round_trip_test() {
+        let date = DateSegments::new(2020, 2, 29, 23);
+
+        assert_eq!(
+            DateSegments::from_hours(0),
+            DateSegments::new(1970, 1, 1, 0)
+        );
+        assert_eq!(DateSegments::from_hours(date.hours_since_epoch()), date);
+        assert_eq!(date.add_hours(1), DateSegments::new(2020, 3, 1, 0));
+    }
+}
@@ -25,8 +25,8 @@ fn read_b_int32<R: Read>(reader: &mut R) -> io::Result<i32> {
 pub fn get_endian<R: Read>(reader: &mut R) -> io::Result<Endian> {
     let word = read_b_int32(reader);
 
-    word.and_then(|int| match int {
-        16 => Ok(Endian::Big),
-        _ => Ok(Endian::Little),
+    word.map(|int| match int {
+        16 => Endian::Big,
+        _ => Endian::Little,
     })
 }
@@ -1,4 +1,4 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -22,6 +22,77 @@ impl Feature {
 
     pub fn csv_row(&self) -> String {
         // long lat value
-        String::from(format!("{},{},{}", self.point.x, self.point.y, self.value))
+        format!("{},{},{}", self.point.x, self.point.y, self.value)
+    }
+}
+
+// a polygon with an exterior ring and any number of holes, rings do not need to be closed
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    pub exterior: Vec<Point>,
+    pub holes: Vec<Vec<Point>>,
+}
+
+impl Polygon {
+    pub fn new(exterior: Vec<Point>, holes: Vec<Vec<Point>>) -> Self {
+        Polygon { exterior, holes }
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        ring_contains(&self.exterior, point) && !self.holes.iter().any(|h| ring_contains(h, point))
+    }
+
+    // min and max corners
+    pub fn bounds(&self) -> (Point, Point) {
+        self.exterior.iter().fold(
+            (
+                Point::new(f64::MAX, f64::MAX),
+                Point::new(f64::MIN, f64::MIN),
+            ),
+            |(min, max), p| {
+                (
+                    Point::new(min.x.min(p.x), min.y.min(p.y)),
+                    Point::new(max.x.max(p.x), max.y.max(p.y)),
+                )
+            },
+        )
+    }
+}
+
+// even-odd ray casting
+fn ring_contains(ring: &[Point], point: Point) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for (i, a) in ring.iter().enumerate() {
+        let b = ring[j];
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_contains_test() {
+        let square = |min: f64, max: f64| {
+            vec![
+                Point::new(min, min),
+                Point::new(max, min),
+                Point::new(max, max),
+                Point::new(min, max),
+            ]
+        };
+        let polygon = Polygon::new(square(0.0, 10.0), vec![square(4.0, 6.0)]);
+
+        assert!(polygon.contains(Point::new(1.0, 1.0)));
+        assert!(!polygon.contains(Point::new(5.0, 5.0)));
+        assert!(!polygon.contains(Point::new(11.0, 5.0)));
     }
 }
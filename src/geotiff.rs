@@ -0,0 +1,298 @@
+use crate::hrap::HrapProjection;
+use crate::utils::{from_mm, MISSING};
+use crate::XmrgData;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+// single band, single strip, little endian GeoTIFF in the polar stereographic metres of HrapProjection
+
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const ASCII: u16 = 2;
+const DOUBLE: u16 = 12;
+
+const USER_DEFINED: u16 = 32767;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SampleType {
+    Int16,   // hundredths of a millimeter, as stored in XMRG, so at most 327.67mm
+    Float32, // millimeters
+}
+
+pub fn write_geotiff(path: &str, data: &XmrgData, sample_type: SampleType) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_geotiff_to(&mut writer, data, sample_type)?;
+    writer.flush()
+}
+
+pub fn write_geotiff_to<W: Write>(
+    writer: &mut W,
+    data: &XmrgData,
+    sample_type: SampleType,
+) -> io::Result<()> {
+    if data.header.columns() <= 0 || data.header.rows() <= 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot write an empty grid as a GeoTIFF",
+        ));
+    }
+
+    let columns = data.header.columns() as u32;
+    let rows = data.header.rows() as u32;
+
+    // rows are stored south to north, tiffs are north up
+    let mut image = Vec::new();
+    for value in data.values.iter().rev().flatten() {
+        match sample_type {
+            SampleType::Int16 => image.extend(int16_sample(*value)?.to_le_bytes()),
+            SampleType::Float32 => image.extend((*value as f32).to_le_bytes()),
+        }
+    }
+
+    let (bits, sample_format, nodata) = match sample_type {
+        SampleType::Int16 => (16, 2, String::from("-999")),
+        SampleType::Float32 => (32, 3, format!("{}", MISSING)),
+    };
+
+    // the outer edge of the upper left cell in projected meters, grid coordinates are cell corners
+    let projection = data.header.projection();
+    let cell_m = projection.mesh_m;
+    let tie_x = (f64::from(data.header.xor()) - projection.pole_x) * cell_m;
+    let tie_y = (f64::from(data.header.yor() + data.header.rows()) - projection.pole_y) * cell_m;
+
+    let (geo_keys, geo_doubles, geo_ascii) = geo_keys(&projection);
+
+    // the image follows the tiff header
+    let image_offset = 8;
+
+    let entries = [
+        Entry::long(256, columns),
+        Entry::long(257, rows),
+        Entry::shorts(258, &[bits]),
+        Entry::shorts(259, &[1]), // no compression
+        Entry::shorts(262, &[1]), // black is zero
+        Entry::long(273, image_offset),
+        Entry::shorts(277, &[1]),
+        Entry::long(278, rows),
+        Entry::long(279, image.len() as u32),
+        Entry::shorts(284, &[1]),
+        Entry::shorts(339, &[sample_format]),
+        Entry::doubles(33550, &[cell_m, cell_m, 0.0]),
+        Entry::doubles(33922, &[0.0, 0.0, 0.0, tie_x, tie_y, 0.0]),
+        Entry::shorts(34735, &geo_keys),
+        Entry::doubles(34736, &geo_doubles),
+        Entry::ascii(34737, &geo_ascii),
+        Entry::ascii(42113, &nodata), // GDAL_NODATA
+    ];
+
+    // values too big for an entry are written between the image and the IFD
+    let mut offset = image_offset + image.len() as u32;
+    offset += offset % 2;
+    let mut extra = Vec::new();
+    let mut ifd = Vec::new();
+    ifd.extend(&(entries.len() as u16).to_le_bytes());
+    for entry in entries.iter() {
+        ifd.extend(&entry.tag.to_le_bytes());
+        ifd.extend(&entry.field_type.to_le_bytes());
+        ifd.extend(&entry.count.to_le_bytes());
+        if entry.bytes.len() <= 4 {
+            let mut inline = entry.bytes.clone();
+            inline.resize(4, 0);
+            ifd.extend(inline);
+        } else {
+            ifd.extend(&(offset + extra.len() as u32).to_le_bytes());
+            extra.extend(&entry.bytes);
+            if extra.len() % 2 == 1 {
+                extra.push(0);
+            }
+        }
+    }
+    ifd.extend(&0u32.to_le_bytes()); // no more IFDs
+
+    let ifd_offset = offset + extra.len() as u32;
+
+    writer.write_all(b"II")?;
+    writer.write_all(&42u16.to_le_bytes())?;
+    writer.write_all(&ifd_offset.to_le_bytes())?;
+    writer.write_all(&image)?;
+    if image.len() % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    writer.write_all(&extra)?;
+    writer.write_all(&ifd)
+}
+
+// Int16 samples are XMRG hundredths of a millimeter, too small for totals above 327.67mm
+fn int16_sample(value: f64) -> io::Result<i16> {
+    from_mm(value).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}, write the GeoTIFF with SampleType::Float32", e),
+        )
+    })
+}
+
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    bytes: Vec<u8>,
+}
+
+impl Entry {
+    fn shorts(tag: u16, values: &[u16]) -> Self {
+        Entry {
+            tag,
+            field_type: SHORT,
+            count: values.len() as u32,
+            bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn long(tag: u16, value: u32) -> Self {
+        Entry {
+            tag,
+            field_type: LONG,
+            count: 1,
+            bytes: value.to_le_bytes().to_vec(),
+        }
+    }
+
+    fn doubles(tag: u16, values: &[f64]) -> Self {
+        Entry {
+            tag,
+            field_type: DOUBLE,
+            count: values.len() as u32,
+            bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn ascii(tag: u16, value: &str) -> Self {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        Entry {
+            tag,
+            field_type: ASCII,
+            count: bytes.len() as u32,
+            bytes,
+        }
+    }
+}
+
+// the GeoKeyDirectory, its double parameters and its ascii parameters
+fn geo_keys(projection: &HrapProjection) -> (Vec<u16>, Vec<f64>, String) {
+    let citation = "HRAP polar stereographic|";
+    let geog_citation = "HRAP sphere|";
+    let ascii = format!("{}{}", citation, geog_citation);
+
+    let doubles = vec![
+        projection.earth_radius_m,   // semi major axis
+        projection.earth_radius_m,   // semi minor axis
+        projection.true_latitude,    // latitude of true scale
+        0.0,                         // false easting
+        0.0,                         // false northing
+        1.0,                         // scale
+        projection.central_meridian, // straight vertical pole longitude
+    ];
+
+    // key id, tag location, count, value or index
+    let keys: Vec<[u16; 4]> = vec![
+        [1024, 0, 1, 1], // projected model
+        [1025, 0, 1, 1], // pixel is area
+        [1026, 34737, citation.len() as u16, 0],
+        [2048, 0, 1, USER_DEFINED], // geographic type
+        [
+            2049,
+            34737,
+            geog_citation.len() as u16,
+            citation.len() as u16,
+        ],
+        [2050, 0, 1, USER_DEFINED], // datum
+        [2054, 0, 1, 9102],         // degrees
+        [2056, 0, 1, USER_DEFINED], // ellipsoid
+        [2057, 34736, 1, 0],
+        [2058, 34736, 1, 1],
+        [3072, 0, 1, USER_DEFINED], // projected cs
+        [3074, 0, 1, USER_DEFINED], // projection
+        [3075, 0, 1, 15],           // polar stereographic
+        [3076, 0, 1, 9001],         // meters
+        [3081, 34736, 1, 2],
+        [3082, 34736, 1, 3],
+        [3083, 34736, 1, 4],
+        [3092, 34736, 1, 5],
+        [3095, 34736, 1, 6],
+    ];
+
+    let mut directory = vec![1, 1, 0, keys.len() as u16];
+    directory.extend(keys.iter().flatten());
+
+    (directory, doubles, ascii)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+    use std::convert::TryInto;
+
+    fn read_u16(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn write_geotiff_test() {
+        let header = Header::new(401, 1601, 3, 2);
+        let data = XmrgData::new(
+            header,
+            None,
+            vec![vec![1.0, 2.0, MISSING], vec![4.0, 5.0, 6.0]],
+        );
+
+        let mut bytes = Vec::new();
+        write_geotiff_to(&mut bytes, &data, SampleType::Int16).unwrap();
+
+        assert_eq!(&bytes[..4], b"II*\0");
+        // north row first
+        assert_eq!(read_u16(&bytes, 8), 400);
+        assert_eq!(read_u16(&bytes, 14) as i16, 100);
+        assert_eq!(read_u16(&bytes, 18) as i16, -999);
+
+        let ifd = read_u32(&bytes, 4) as usize;
+        let count = read_u16(&bytes, ifd) as usize;
+        let tags: Vec<u16> = (0..count)
+            .map(|i| read_u16(&bytes, ifd + 2 + i * 12))
+            .collect();
+        let mut sorted = tags.clone();
+        sorted.sort_unstable();
+        assert_eq!(tags, sorted);
+
+        // the tie point is the upper left corner, the pole is the lower left corner of the grid
+        let tiepoint = (0..count)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|at| read_u16(&bytes, *at) == 33922)
+            .unwrap();
+        let at = read_u32(&bytes, tiepoint + 8) as usize + 24;
+        let tie_x = f64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let tie_y = f64::from_le_bytes(bytes[at + 8..at + 16].try_into().unwrap());
+        assert_eq!(tie_x, 0.0);
+        assert_eq!(tie_y, 2.0 * 4762.5);
+    }
+
+    #[test]
+    fn write_geotiff_overflow_test() {
+        let data = XmrgData::new(Header::new(401, 1601, 2, 1), None, vec![vec![1.0, 500.0]]);
+
+        let error = write_geotiff_to(&mut Vec::new(), &data, SampleType::Int16).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("Float32"));
+
+        assert!(write_geotiff_to(&mut Vec::new(), &data, SampleType::Float32).is_ok());
+    }
+}
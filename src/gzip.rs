@@ -0,0 +1,516 @@
+use crate::checksum::Crc32;
+
+use std::io;
+use std::io::prelude::*;
+
+// gzip decompression (RFC 1952, 1951) for XMRG archives, which are distributed as .gz files.
+// Data is inflated as it is read, holding only the 32 KiB deflate window and what has not been read yet.
+// Concatenated members are joined, each member's CRC-32 and length are checked, and zero bytes padding the
+// end of the file are skipped as gzip -d does
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const FTEXT_FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+// the furthest a deflate back reference can reach
+const WINDOW: usize = 32768;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+pub fn gunzip(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    GzDecoder::new(bytes).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[derive(PartialEq)]
+enum State {
+    Header,
+    Block,
+    Codes,
+    Trailer,
+    Done,
+}
+
+pub struct GzDecoder<R> {
+    bits: Bits<R>,
+    state: State,
+    members: usize,
+    last_block: bool,
+    codes: Option<(Huffman, Huffman)>,
+    out: Vec<u8>,   // the window followed by inflated bytes not yet read
+    at: usize,      // the next byte of out to read
+    checked: usize, // out before this is in the CRC of the member
+    crc: Crc32,
+    size: u32,
+}
+
+impl<R: BufRead> GzDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        GzDecoder {
+            bits: Bits::new(reader),
+            state: State::Header,
+            members: 0,
+            last_block: false,
+            codes: None,
+            out: Vec::new(),
+            at: 0,
+            checked: 0,
+            crc: Crc32::new(),
+            size: 0,
+        }
+    }
+
+    // inflates until a window's worth is waiting to be read or the file ends
+    fn fill(&mut self) -> io::Result<()> {
+        if self.at > 2 * WINDOW {
+            self.check();
+            let drop = self.at - WINDOW;
+            self.out.drain(..drop);
+            self.at -= drop;
+            self.checked -= drop;
+        }
+
+        while self.out.len() - self.at < WINDOW && self.state != State::Done {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    fn step(&mut self) -> io::Result<()> {
+        match self.state {
+            State::Header => {
+                self.state = if self.member_header()? {
+                    self.members += 1;
+                    State::Block
+                } else {
+                    State::Done
+                };
+            }
+            State::Block => {
+                self.last_block = self.bits.read(1)? == 1;
+                match self.bits.read(2)? {
+                    0 => {
+                        stored(&mut self.bits, &mut self.out)?;
+                        self.end_block();
+                    }
+                    1 => {
+                        self.codes = Some(fixed_codes());
+                        self.state = State::Codes;
+                    }
+                    2 => {
+                        self.codes = Some(dynamic_codes(&mut self.bits)?);
+                        self.state = State::Codes;
+                    }
+                    _ => return Err(invalid("invalid deflate block type")),
+                }
+            }
+            State::Codes => {
+                if let Some((literals, distances)) = &self.codes {
+                    if symbol(&mut self.bits, &mut self.out, literals, distances)? {
+                        self.end_block();
+                    }
+                }
+            }
+            State::Trailer => {
+                // the CRC32 and length follow the byte aligned end of the deflate stream
+                self.bits.align();
+                let mut trailer = [0u8; 8];
+                for byte in trailer.iter_mut() {
+                    *byte = self.bits.byte("truncated gzip file")?;
+                }
+                let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+                let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+
+                self.check();
+                if size != self.size {
+                    return Err(invalid("gzip length does not match the data"));
+                }
+                if self.crc.finish() != crc {
+                    return Err(invalid(
+                        "gzip CRC does not match the data, the file is corrupt",
+                    ));
+                }
+                self.crc = Crc32::new();
+                self.size = 0;
+                self.state = State::Header;
+            }
+            State::Done => {}
+        }
+        Ok(())
+    }
+
+    fn end_block(&mut self) {
+        self.codes = None;
+        self.state = if self.last_block {
+            State::Trailer
+        } else {
+            State::Block
+        };
+    }
+
+    // adds the bytes inflated since the last check to the member's CRC and length
+    fn check(&mut self) {
+        self.crc.update(&self.out[self.checked..]);
+        self.size = self
+            .size
+            .wrapping_add((self.out.len() - self.checked) as u32);
+        self.checked = self.out.len();
+    }
+
+    // skips a member header, false at the end of the file
+    fn member_header(&mut self) -> io::Result<bool> {
+        let first = match self.bits.next_byte()? {
+            None if self.members > 0 => return Ok(false),
+            Some(0) if self.members > 0 => {
+                while let Some(byte) = self.bits.next_byte()? {
+                    if byte != 0 {
+                        return Err(invalid("unexpected data after the end of the gzip file"));
+                    }
+                }
+                return Ok(false);
+            }
+            first => first,
+        };
+
+        let mut header = [0u8; 10];
+        header[0] = first.unwrap_or(0);
+        for byte in header.iter_mut().skip(1) {
+            *byte = self.bits.next_byte()?.unwrap_or(0);
+        }
+        if !is_gzip(&header) || header[2] != 8 {
+            return Err(invalid("not a deflate compressed gzip file"));
+        }
+
+        let flags = header[3];
+        if flags & FEXTRA != 0 {
+            let low = self.bits.byte("truncated gzip header")?;
+            let high = self.bits.byte("truncated gzip header")?;
+            for _ in 0..u16::from_le_bytes([low, high]) {
+                self.bits.byte("truncated gzip header")?;
+            }
+        }
+        for flag in [FNAME, FCOMMENT].iter() {
+            if flags & flag != 0 {
+                while self.bits.byte("unterminated gzip header field")? != 0 {}
+            }
+        }
+        if flags & FTEXT_FHCRC != 0 {
+            self.bits.byte("truncated gzip header")?;
+            self.bits.byte("truncated gzip header")?;
+        }
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.at == self.out.len() {
+            self.fill()?;
+        }
+        let n = buf.len().min(self.out.len() - self.at);
+        buf[..n].copy_from_slice(&self.out[self.at..self.at + n]);
+        self.at += n;
+        Ok(n)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// deflate reads bits from the least significant end of each byte
+struct Bits<R> {
+    reader: R,
+    buffer: u32,
+    count: u32,
+}
+
+impl<R: BufRead> Bits<R> {
+    fn new(reader: R) -> Self {
+        Bits {
+            reader,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.reader.fill_buf()?.first().copied();
+        if byte.is_some() {
+            self.reader.consume(1);
+        }
+        Ok(byte)
+    }
+
+    // a whole byte, when the bits are aligned
+    fn byte(&mut self, message: &str) -> io::Result<u8> {
+        self.next_byte()?.ok_or_else(|| invalid(message))
+    }
+
+    fn read(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = self.byte("truncated deflate data")?;
+            self.buffer |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u32 << n) - 1);
+        self.buffer = if n == 32 { 0 } else { self.buffer >> n };
+        self.count -= n;
+        Ok(value)
+    }
+
+    // drops the rest of a partly read byte, whole bytes are never buffered
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// canonical Huffman codes as counts of codes of each length and symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[usize::from(*length)] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                let length = usize::from(*length);
+                symbols[usize::from(offsets[length])] = symbol as u16;
+                offsets[length] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode<R: BufRead>(&self, bits: &mut Bits<R>) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= bits.read(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid deflate code"))
+    }
+}
+
+fn stored<R: BufRead>(bits: &mut Bits<R>, out: &mut Vec<u8>) -> io::Result<()> {
+    bits.align();
+    let len = bits.read(16)?;
+    let nlen = bits.read(16)?;
+    if len != !nlen & 0xffff {
+        return Err(invalid("invalid stored block length"));
+    }
+    for _ in 0..len {
+        out.push(bits.read(8)? as u8);
+    }
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes<R: BufRead>(bits: &mut Bits<R>) -> io::Result<(Huffman, Huffman)> {
+    let literals = bits.read(5)? as usize + 257;
+    let distances = bits.read(5)? as usize + 1;
+    let code_lengths = bits.read(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for i in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+        lengths[*i] = bits.read(3)? as u8;
+    }
+    let length_code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let symbol = length_code.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("repeated code length with no previous length"))?;
+                (previous, 3 + bits.read(2)?)
+            }
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literals + distances {
+        return Err(invalid("too many code lengths"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+// inflates one literal or back reference, true at the end of the block
+fn symbol<R: BufRead>(
+    bits: &mut Bits<R>,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<bool> {
+    let symbol = usize::from(literals.decode(bits)?);
+    match symbol {
+        0..=255 => out.push(symbol as u8),
+        256 => return Ok(true),
+        _ => {
+            let i = symbol - 257;
+            if i >= LENGTH_BASE.len() {
+                return Err(invalid("invalid deflate length"));
+            }
+            let length =
+                usize::from(LENGTH_BASE[i]) + bits.read(u32::from(LENGTH_EXTRA[i]))? as usize;
+
+            let d = usize::from(distances.decode(bits)?);
+            if d >= DISTANCE_BASE.len() {
+                return Err(invalid("invalid deflate distance"));
+            }
+            let distance =
+                usize::from(DISTANCE_BASE[d]) + bits.read(u32::from(DISTANCE_EXTRA[d]))? as usize;
+            if distance > out.len() {
+                return Err(invalid("deflate distance before the start of the data"));
+            }
+
+            let from = out.len() - distance;
+            for k in 0..length {
+                out.push(out[from + k]);
+            }
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gunzip_test() {
+        // printf 'hello hello hello hello\n' | gzip -n, a fixed Huffman block with a back reference
+        let fixed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+            0xc9, 0x57, 0xc8, 0x40, 0x27, 0xb9, 0x00, 0x00, 0x88, 0x59, 0x0b, 0x18, 0x00, 0x00,
+            0x00,
+        ];
+        assert!(is_gzip(&fixed));
+        assert_eq!(
+            gunzip(&fixed).unwrap(),
+            b"hello hello hello hello\n".to_vec()
+        );
+
+        // a stored block, as written by gzip for data it cannot compress, twice
+        let mut stored = vec![0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0x03];
+        stored.extend(&[0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c']);
+        stored.extend(&[0xc2, 0x41, 0x24, 0x35, 3, 0, 0, 0]);
+        let twice: Vec<u8> = stored.iter().chain(stored.iter()).copied().collect();
+        assert_eq!(gunzip(&twice).unwrap(), b"abcabc".to_vec());
+
+        assert!(gunzip(&fixed[..20]).is_err());
+
+        // a damaged byte that leaves the length right is caught by the CRC
+        let mut corrupt = stored.clone();
+        corrupt[15] = b'x';
+        assert!(gunzip(&corrupt).is_err());
+
+        // zero padding after the last member is skipped, anything else is not
+        let mut padded = stored.clone();
+        padded.extend(&[0; 512]);
+        assert_eq!(gunzip(&padded).unwrap(), b"abc".to_vec());
+        padded.push(1);
+        assert!(gunzip(&padded).is_err());
+        assert!(gunzip(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn gunzip_beyond_window_test() {
+        // 100 000 bytes in two 50 000 byte stored blocks, decoded through a small buffer so the window is
+        // trimmed many times
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut check = Crc32::new();
+        check.update(&data);
+
+        let mut gz = vec![0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0x03];
+        for (i, block) in data.chunks(50_000).enumerate() {
+            let len = block.len() as u16;
+            gz.push(u8::from(i == 1));
+            gz.extend(&len.to_le_bytes());
+            gz.extend(&(!len).to_le_bytes());
+            gz.extend(block);
+        }
+        gz.extend(&check.finish().to_le_bytes());
+        gz.extend(&(data.len() as u32).to_le_bytes());
+
+        let mut decoder = GzDecoder::new(&gz[..]);
+        let mut out: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 1000];
+        loop {
+            let n = decoder.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend(&buf[..n]);
+            assert!(decoder.out.len() <= 3 * WINDOW + 65535);
+        }
+        assert_eq!(out, data);
+    }
+}
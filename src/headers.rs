@@ -1,6 +1,9 @@
+use crate::datetime::DateSegments;
 use crate::endian::Endian;
-use crate::geo::Point;
-use crate::hrap::hrap_to_latlon;
+use crate::geo::{Point, Polygon};
+use crate::hrap::{
+    hrap_cell_area_km2, hrap_to_latlon_with, Coordinates, HrapProjection, LonConvention,
+};
 use crate::read_bytes::ReadBytes;
 use std::io;
 use std::io::prelude::*;
@@ -10,12 +13,42 @@ const YOR: usize = 1;
 const COLUMNS: usize = 2;
 const ROWS: usize = 3;
 
+// the subdivisions of an HRAP cell in use, SHRAP is 4
+const FACTORS: [i32; 4] = [1, 2, 4, MAX_FACTOR];
+pub(crate) const MAX_FACTOR: i32 = 10;
+
+// the east and north edges of the national HRAP grid, NCEP grid 240 of 1121 x 881 cells from HRAP (1, 1)
+pub(crate) const NATIONAL_X_END: i32 = 1122;
+pub(crate) const NATIONAL_Y_END: i32 = 882;
+
+// HRAP cell (i, j) covers i..i + 1 and j..j + 1 in HRAP coordinates, as in the NWS xmrgtoasc and xmrgtolist
+// programs: integer coordinates are the lower left corners of cells and cell centres are at i + 0.5, j + 0.5.
+// A header's xor and yor are the lower left corner of its first cell
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CellPosition {
+    Centre,
+    LowerLeft,
+}
+
+impl CellPosition {
+    fn offset(&self) -> f64 {
+        match self {
+            CellPosition::Centre => 0.5,
+            CellPosition::LowerLeft => 0.0,
+        }
+    }
+}
+
+// the location of each cell of a header, row by row from the south west like XmrgData values
 pub struct CoordinateGenerator {
     start_x: i32,
     current_x: i32,
     current_y: i32,
     x_end: i32,
     y_end: i32,
+    cell_size: f64,
+    position: CellPosition,
+    output: Coordinates,
 }
 
 impl Iterator for CoordinateGenerator {
@@ -29,34 +62,217 @@ impl Iterator for CoordinateGenerator {
         }
 
         if self.current_y != self.y_end {
-            Some(hrap_to_latlon(
-                f64::from(self.current_x),
-                f64::from(self.current_y),
-            ))
+            let offset = self.position.offset();
+            let x = (f64::from(self.current_x) + offset) * self.cell_size;
+            let y = (f64::from(self.current_y) + offset) * self.cell_size;
+            Some(self.output.locate(x, y))
         } else {
             None
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+// xor, yor and cell indices are in units of the grid, which may subdivide HRAP cells. Quarter HRAP (SHRAP)
+// grids have a factor of 4, so their xor is 4 times the HRAP x of the same point
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Header {
     xor: i32,
     yor: i32,
     columns: i32,
     rows: i32,
+    factor: i32,
 }
 
 impl Header {
+    pub fn new(xor: i32, yor: i32, columns: i32, rows: i32) -> Self {
+        Self {
+            xor,
+            yor,
+            columns,
+            rows,
+            factor: 1,
+        }
+    }
+
     pub fn from_vec(vec: Vec<i32>) -> Self {
+        Self::new(vec[XOR], vec[YOR], vec[COLUMNS], vec[ROWS])
+    }
+
+    // the number of grid cells along each side of an HRAP cell, one of 1, 2, 4 (SHRAP) or 10
+    pub fn with_factor(self, factor: i32) -> io::Result<Self> {
+        if !FACTORS.contains(&factor) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "HRAP subdivision factor must be one of {:?}, not {}",
+                    FACTORS, factor
+                ),
+            ));
+        }
+        Ok(Self { factor, ..self })
+    }
+
+    // the factor of another, already valid, header
+    pub(crate) fn with_factor_of(self, other: &Header) -> Self {
         Self {
-            xor: vec[XOR],
-            yor: vec[YOR],
-            columns: vec[COLUMNS],
-            rows: vec[ROWS],
+            factor: other.factor,
+            ..self
+        }
+    }
+
+    // 1 if the window fits inside the national HRAP grid, otherwise 4 (SHRAP) if it fits at that, otherwise 10,
+    // e.g. 4 for the 4484 x 3524 national SHRAP grid. Factor 2 grids are read as SHRAP, and a SHRAP window small
+    // enough to fit inside the HRAP grid as HRAP, so those need their factor from elsewhere, e.g. factor_from_path
+    pub fn factor_from_window(&self) -> i32 {
+        [1, 4, 10]
+            .iter()
+            .copied()
+            .find(|factor| {
+                self.xor + self.columns <= NATIONAL_X_END * factor
+                    && self.yor + self.rows <= NATIONAL_Y_END * factor
+            })
+            .unwrap_or(10)
+    }
+
+    // the factor a file's name implies, 4 for SHRAP products and otherwise 1
+    pub fn factor_from_path(path: &str) -> i32 {
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+        if name.to_lowercase().contains("shrap") {
+            4
+        } else {
+            1
         }
     }
 
+    pub fn xor(&self) -> i32 {
+        self.xor
+    }
+
+    pub fn yor(&self) -> i32 {
+        self.yor
+    }
+
+    pub fn columns(&self) -> i32 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> i32 {
+        self.rows
+    }
+
+    pub fn factor(&self) -> i32 {
+        self.factor
+    }
+
+    // the projection of grid coordinates, those of xor and yor
+    pub fn projection(&self) -> HrapProjection {
+        HrapProjection::subdivided(self.factor)
+    }
+
+    // the side of a cell in HRAP units
+    pub fn cell_size(&self) -> f64 {
+        1.0 / f64::from(self.factor)
+    }
+
+    // the HRAP coordinate of the centre of a cell of the grid
+    pub fn hrap_point(&self, column: usize, row: usize) -> Point {
+        self.cell_point(column, row, CellPosition::Centre)
+    }
+
+    pub fn cell_point(&self, column: usize, row: usize, position: CellPosition) -> Point {
+        Point::new(
+            (f64::from(self.xor) + column as f64 + position.offset()) * self.cell_size(),
+            (f64::from(self.yor) + row as f64 + position.offset()) * self.cell_size(),
+        )
+    }
+
+    // the inverse of hrap_point, the fractional column and row of an HRAP coordinate relative to cell centres,
+    // so rounding gives the cell holding it
+    pub fn grid_position(&self, hrap: Point) -> Point {
+        Point::new(
+            hrap.x * f64::from(self.factor) - f64::from(self.xor) - 0.5,
+            hrap.y * f64::from(self.factor) - f64::from(self.yor) - 0.5,
+        )
+    }
+
+    // the location of every cell, see CellPosition and Coordinates
+    pub fn coordinates(&self, position: CellPosition, output: Coordinates) -> CoordinateGenerator {
+        CoordinateGenerator {
+            start_x: self.xor,
+            current_x: self.xor - 1,
+            current_y: self.yor,
+            x_end: self.xor + self.columns,
+            y_end: self.yor + self.rows,
+            cell_size: self.cell_size(),
+            position,
+            output,
+        }
+    }
+
+    // the outline of a cell, counter clockwise from the south west corner. Longitudes are signed (negative west)
+    pub fn cell_polygon(&self, column: usize, row: usize) -> Polygon {
+        let corner = self.cell_point(column, row, CellPosition::LowerLeft);
+        let size = self.cell_size();
+        let corners = [(0.0, 0.0), (size, 0.0), (size, size), (0.0, size)]
+            .iter()
+            .map(|(dx, dy)| {
+                hrap_to_latlon_with(corner.x + dx, corner.y + dy, LonConvention::Signed)
+            })
+            .collect();
+        Polygon::new(corners, vec![])
+    }
+
+    // the ground area of a cell in km2
+    pub fn cell_area_km2(&self, column: usize, row: usize) -> f64 {
+        let centre = self.hrap_point(column, row);
+        hrap_cell_area_km2(centre.x, centre.y) * self.cell_size() * self.cell_size()
+    }
+
+    // the window covered by both headers, None if they do not overlap or subdivide HRAP differently
+    pub fn intersection(&self, other: &Header) -> Option<Header> {
+        if self.factor != other.factor {
+            return None;
+        }
+
+        let xor = self.xor.max(other.xor);
+        let yor = self.yor.max(other.yor);
+        let x_end = (self.xor + self.columns).min(other.xor + other.columns);
+        let y_end = (self.yor + self.rows).min(other.yor + other.rows);
+
+        if x_end > xor && y_end > yor {
+            Some(Header::new(xor, yor, x_end - xor, y_end - yor).with_factor_of(self))
+        } else {
+            None
+        }
+    }
+
+    // an InvalidInput error unless both headers subdivide HRAP cells the same way, so their windows align
+    pub fn check_factor(&self, other: &Header) -> io::Result<()> {
+        if self.factor == other.factor {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "grids with HRAP subdivision factors {} and {} do not align",
+                    self.factor, other.factor
+                ),
+            ))
+        }
+    }
+
+    // the smallest window covering both headers, an error if their factors differ
+    pub fn union(&self, other: &Header) -> io::Result<Header> {
+        self.check_factor(other)?;
+
+        let xor = self.xor.min(other.xor);
+        let yor = self.yor.min(other.yor);
+        let x_end = (self.xor + self.columns).max(other.xor + other.columns);
+        let y_end = (self.yor + self.rows).max(other.yor + other.rows);
+
+        Ok(Header::new(xor, yor, x_end - xor, y_end - yor).with_factor_of(self))
+    }
+
     // pub fn generate_coordinates(&self) -> Vec<Vec<Point>> {
     //     (self.yor..self.rows).map(|y| {
     //         (self.xor..self.columns).map(|x| {
@@ -72,14 +288,12 @@ impl IntoIterator for Header {
     type Item = Point;
     type IntoIter = CoordinateGenerator;
 
+    // cell centres with positive west longitudes
     fn into_iter(self) -> Self::IntoIter {
-        CoordinateGenerator {
-            start_x: self.xor,
-            current_x: self.xor - 1,
-            current_y: self.yor,
-            x_end: self.xor + self.columns,
-            y_end: self.yor + self.rows,
-        }
+        self.coordinates(
+            CellPosition::Centre,
+            Coordinates::LatLon(LonConvention::PositiveWest),
+        )
     }
 }
 
@@ -201,9 +415,9 @@ pub fn build_1997_reader<R: Read>(reader: &mut R, endian: Endian) -> io::Result<
     let saved_datetime_b = ReadBytes::new(20, endian).read_u8s(reader)?;
     let process_flag_b = ReadBytes::new(8, endian).read_u8s(reader)?;
 
-    let user_id = String::from_utf8(user_id_b).unwrap_or(String::default());
-    let saved_datetime_b = String::from_utf8(saved_datetime_b).unwrap_or(String::default());
-    let process_flag = String::from_utf8(process_flag_b).unwrap_or(String::default());
+    let user_id = String::from_utf8(user_id_b).unwrap_or_default();
+    let saved_datetime_b = String::from_utf8(saved_datetime_b).unwrap_or_default();
+    let process_flag = String::from_utf8(process_flag_b).unwrap_or_default();
 
     Ok(Build1997Header::new(
         user_id,
@@ -220,7 +434,7 @@ pub fn build_4_2_add_reader<R: Read>(
     let max_value = endian.read(reader)?;
     let version_number = endian.read(reader)?;
 
-    let valid_datetime = String::from_utf8(valid_datetime_bytes).unwrap_or(String::default());
+    let valid_datetime = String::from_utf8(valid_datetime_bytes).unwrap_or_default();
 
     Ok(Build4_2Additions::new(
         valid_datetime,
@@ -245,18 +459,15 @@ pub fn build_5_2_2_reader<R: Read>(reader: &mut R, endian: Endian) -> io::Result
     let process_flag = read_8.read_u8s(reader)?;
     let build_4_2_additions = build_4_2_add_reader(reader, endian)?;
 
-    let op = match String::from_utf8(op_bytes)
-        .unwrap_or(String::default())
-        .as_ref()
-    {
+    let op = match String::from_utf8(op_bytes).unwrap_or_default().as_ref() {
         "LX" => OperSys::LX,
         "HP" => OperSys::HP,
         _ => OperSys::Unknown,
     };
 
-    let u_id = String::from_utf8(user_id).unwrap_or(String::default());
-    let s_dt = String::from_utf8(saved_datetime).unwrap_or(String::default());
-    let p_flag = String::from_utf8(process_flag).unwrap_or(String::default());
+    let u_id = String::from_utf8(user_id).unwrap_or_default();
+    let s_dt = String::from_utf8(saved_datetime).unwrap_or_default();
+    let p_flag = String::from_utf8(process_flag).unwrap_or_default();
 
     Ok(Build5_2_2Header::new_2(
         op,
@@ -274,6 +485,33 @@ pub enum Metadata {
 }
 
 impl Metadata {
+    // metadata for grids produced by rexmrg rather than read from a file, e.g. accumulations. The saved time is
+    // the valid time rather than the clock, so the same inputs always give byte for byte the same file
+    pub fn for_product(valid_time: DateSegments, process_flag: &str, values: &[Vec<f64>]) -> Self {
+        let max_value = values
+            .iter()
+            .flatten()
+            .map(|value| {
+                if *value < 0.0 {
+                    -999
+                } else {
+                    (value * 100.0).round() as i32
+                }
+            })
+            .max()
+            .unwrap_or_default();
+
+        Metadata::Header5_2_2(Build5_2_2Header::new(
+            OperSys::LX,
+            String::from("rexmrg"),
+            valid_time.str_from_date(),
+            String::from(process_flag),
+            valid_time.str_from_date(),
+            max_value,
+            0.0,
+        ))
+    }
+
     // DEBUG - come back to this
     pub fn datetime(&self) -> String {
         match self {
@@ -335,79 +573,141 @@ impl Metadata {
     }
 }
 
-// #[derive(Debug, Copy, Clone)]
-// pub struct DateSegments {
-//     month: i32,
-//     day: i32,
-//     year: i32,
-//     hour: i32 // in 24 hour time
-// }
-
-// impl DateSegments {
-//     // look into better strategy than indexing
-//     pub fn from_chars(chars: &str) -> Self {
-//         // assert_eq!(chars.len(), 10);
-//         DateSegments {
-//             month: chars[0..2].parse::<i32>().unwrap_or_default(),
-//             day: chars[2..4].parse::<i32>().unwrap_or_default(),
-//             year: chars[4..8].parse::<i32>().unwrap_or_default(),
-//             hour: chars[8..10].parse::<i32>().unwrap_or_default(),
-//         }
-//     }
-
-//     pub fn str_from_date(&self) -> String {
-//         format!("{}-{}-{} {}:00:00", self.month, self.day, self.month, self.hour)
-//     }
-// }
-
-// pub fn read_xmrg_date(path: &str) -> Option<DateSegments> {
-//     // let date_chars = path.chars().enumerate()
-//     //     // .skip_while(|c| *c < '0' || *c > '9')
-//     //     .skip_while(|c| *c != '_')
-//     //     .collect::<String>();
-//     let mut started = false;
-//     let mut count = 0;
-//     let mut start = 0;
-//     for (i, c) in path.chars().enumerate() {
-//         if c >= '0' && c <= '9' {
-//             if !started {
-//                 start = i;
-//                 started = true;
-//             }
-//             count += 1;
-//         } else {
-//             if started {
-//                 started = false;
-//                 count = 0;
-//             }
-//         }
-
-//         if count == 8 {
-//             return Some(DateSegments::from_chars(&path[start..]))
-//         }
-//     }
-
-//     // DateSegments::from_chars(&date_chars)
-//     None
-// }
-
 #[cfg(test)]
 mod tests {
-    // use super::*;
-
-    // #[test]
-    // fn read_file_name_date_test() {
-    //     let path = "xmrg0506199516z.gz";
+    use super::*;
+    use crate::hrap::hrap_to_latlon;
+
+    #[test]
+    fn coordinates_test() {
+        // the south west corner of the national HRAP grid, the first point of NCEP grid 240 at 23.117N 119.023W
+        // is the centre of its first cell
+        let header = Header::new(1, 1, 1121, 881);
+
+        let centre = header
+            .coordinates(
+                CellPosition::Centre,
+                Coordinates::LatLon(LonConvention::Signed),
+            )
+            .next()
+            .unwrap();
+        assert!((centre.x + 119.023).abs() < 0.001);
+        assert!((centre.y - 23.117).abs() < 0.001);
+
+        let corner = header
+            .coordinates(
+                CellPosition::LowerLeft,
+                Coordinates::LatLon(LonConvention::PositiveWest),
+            )
+            .next()
+            .unwrap();
+        assert_eq!(corner, hrap_to_latlon(1.0, 1.0));
+
+        assert_eq!(header.into_iter().count(), 1121 * 881);
+        let last = header.into_iter().last().unwrap();
+        assert_eq!(last, hrap_to_latlon(1121.5, 881.5));
+
+        let meters = header
+            .coordinates(CellPosition::LowerLeft, Coordinates::Meters)
+            .next()
+            .unwrap();
+        assert_eq!(meters, Point::new(-400.0 * 4762.5, -1600.0 * 4762.5));
+
+        let position = header.grid_position(header.hrap_point(3, 2));
+        assert_eq!(position, Point::new(3.0, 2.0));
+        assert_eq!(
+            header.cell_point(3, 2, CellPosition::LowerLeft),
+            Point::new(4.0, 3.0)
+        );
+    }
 
-    //     let data_segments = read_xmrg_date(&path).unwrap();
+    #[test]
+    fn window_corners_test() {
+        // xor, yor, columns, rows, then the lower left corner and centre of the first cell and of the last cell
+        // as degrees west and north, from the hrap_to_latlon routine of the NWS xmrgtolist.c
+        let windows = [
+            (
+                Header::new(400, 250, 300, 160),
+                [
+                    (105.0424, 33.1562),
+                    (105.0212, 33.1739),
+                    (90.9638, 37.5877),
+                    (90.9355, 37.6011),
+                ],
+            ),
+            (
+                Header::new(850, 500, 150, 120),
+                [
+                    (82.8138, 39.0621),
+                    (82.7824, 39.0723),
+                    (73.6602, 40.5408),
+                    (73.6260, 40.5471),
+                ],
+            ),
+        ];
+
+        for (header, expected) in windows.iter() {
+            let positions = [
+                CellPosition::LowerLeft,
+                CellPosition::Centre,
+                CellPosition::LowerLeft,
+                CellPosition::Centre,
+            ];
+            for (i, (position, (west, north))) in positions.iter().zip(expected.iter()).enumerate()
+            {
+                for (convention, lon) in [
+                    (LonConvention::PositiveWest, *west),
+                    (LonConvention::Signed, -west),
+                ]
+                .iter()
+                {
+                    let mut points =
+                        header.coordinates(*position, Coordinates::LatLon(*convention));
+                    let point = if i < 2 { points.next() } else { points.last() }.unwrap();
+                    assert!((point.x - lon).abs() < 1e-3, "{:?} {:?}", header, point);
+                    assert!((point.y - north).abs() < 1e-3, "{:?} {:?}", header, point);
+                }
+            }
+        }
+    }
 
-    //     assert_eq!(data_segments.month, 5);
-    //     assert_eq!(data_segments.day, 6);
-    //     assert_eq!(data_segments.year, 1995);
-    //     assert_eq!(data_segments.hour, 16);
+    #[test]
+    fn subdivision_test() {
+        let hrap = Header::new(367, 263, 2, 2);
+        let shrap = Header::new(367 * 4, 263 * 4, 8, 8).with_factor(4).unwrap();
+
+        // the same south west corner, and the first SHRAP centre an eighth of an HRAP cell in from it
+        assert_eq!(
+            shrap.cell_point(0, 0, CellPosition::LowerLeft),
+            hrap.cell_point(0, 0, CellPosition::LowerLeft)
+        );
+        assert_eq!(shrap.hrap_point(0, 0), Point::new(367.125, 263.125));
+        assert_eq!(shrap.hrap_point(4, 4), Point::new(368.125, 264.125));
+
+        let position = shrap.grid_position(Point::new(367.6, 263.1));
+        assert_eq!((position.x.round(), position.y.round()), (2.0, 0.0));
+
+        let area = shrap.cell_area_km2(0, 0) * 16.0;
+        assert!((area - hrap.cell_area_km2(0, 0)).abs() < 0.1);
+
+        assert_eq!(shrap.into_iter().count(), 64);
+        assert!(hrap.intersection(&shrap).is_none());
+        let error = hrap.union(&shrap).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        for factor in [0, 3, -4].iter() {
+            let error = hrap.with_factor(*factor).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert_eq!(hrap.with_factor(10).unwrap().factor(), 10);
 
-    //     let p2 = "xmrg6_2020021600f006.gz";
+        assert_eq!(Header::new(0, 0, 4484, 3524).factor_from_window(), 4);
+        assert_eq!(Header::new(4000, 1000, 8, 8).factor_from_window(), 4);
+        assert_eq!(Header::new(1, 1, 1121, 881).factor_from_window(), 1);
+        assert_eq!(shrap.factor_from_window(), 4);
+        assert_eq!(Header::new(400, 100, 8, 8).factor_from_window(), 1);
 
-    //     let ds2 = read_xmrg_date(&p2).unwrap();
-    // }
+        assert_eq!(Header::factor_from_path("/data/SHRAP/xmrg0101200001z"), 1);
+        assert_eq!(Header::factor_from_path("/data/xmrg_shrap_0101200001z"), 4);
+    }
 }
@@ -2,39 +2,235 @@ use crate::geo::Point;
 use std::f64::consts::PI;
 
 // HRAP : https://www.nws.noaa.gov/oh/hrl/nwsrfs/users_manual/part2/_pdf/21hrapgrid.pdf
+// a polar stereographic projection of a sphere of radius 6371.2 km, true at 60N with 105W straight up the grid,
+// in units of a 4762.5 m mesh at 60N with the pole at (401, 1601). Subdivided grids such as SHRAP shrink the mesh
+// and move the pole to keep the same origin, so their coordinates are factor times HRAP coordinates
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HrapProjection {
+    pub earth_radius_m: f64,
+    pub mesh_m: f64,
+    pub true_latitude: f64,
+    pub central_meridian: f64, // signed, -105
+    pub pole_x: f64,
+    pub pole_y: f64,
+}
+
+impl Default for HrapProjection {
+    fn default() -> Self {
+        HrapProjection {
+            earth_radius_m: 6_371_200.0,
+            mesh_m: 4762.5,
+            true_latitude: 60.0,
+            central_meridian: -105.0,
+            pole_x: 401.0,
+            pole_y: 1601.0,
+        }
+    }
+}
+
+impl HrapProjection {
+    pub fn subdivided(factor: i32) -> Self {
+        let hrap = Self::default();
+        let factor = f64::from(factor);
+        HrapProjection {
+            mesh_m: hrap.mesh_m / factor,
+            pole_x: hrap.pole_x * factor,
+            pole_y: hrap.pole_y * factor,
+            ..hrap
+        }
+    }
+
+    // a signed lon/lat to grid coordinates
+    pub fn forward(&self, lon: f64, lat: f64) -> Point {
+        self.forward_positive_west(-lon, lat)
+    }
+
+    // grid coordinates to a signed lon/lat
+    pub fn inverse(&self, x: f64, y: f64) -> Point {
+        let point = self.inverse_positive_west(x, y);
+        Point::new(signed_longitude(point.x), point.y)
+    }
+
+    // derived from https://www.nws.noaa.gov/oh/hrl/dmip/lat_lon.txt
+    fn inverse_positive_west(&self, x: f64, y: f64) -> Point {
+        let stlon = -self.central_meridian;
+        let raddeg = 180.0 / PI;
+        let tlat = self.true_latitude / raddeg;
+
+        let _x = x - self.pole_x; // >
+        let _y = y - self.pole_y; // >
+
+        let rr = (_x * _x) + (_y * _y);
+
+        let gi = (self.earth_radius_m * (1.0 + tlat.sin())) / self.mesh_m;
+        let _gi = gi * gi;
+
+        let rlat = ((_gi - rr) / (_gi + rr)).asin() * raddeg;
+
+        let mut ang = _y.atan2(_x) * raddeg;
+
+        // let if (ang.lt.0.) ang = ang + 360.0;
+        ang += if ang < 0.0 { 360.0 } else { 0.0 };
+
+        let mut rlon = 270.0 + stlon - ang;
+
+        // let if(rlon.lt.0.) rlon=rlon+360.0;
+        rlon += if rlon < 0.0 { 360.0 } else { 0.0 };
+
+        // let if(rlon.gt.360.0) rlon = rlon - 360.0;
+        rlon -= if rlon > 360.0 { 360.0 } else { 0.0 };
+
+        Point::new(rlon, rlat)
+    }
+
+    fn forward_positive_west(&self, lon: f64, lat: f64) -> Point {
+        let stlon = -self.central_meridian;
+        let raddeg = 180.0 / PI;
+        let tlat = self.true_latitude / raddeg;
+
+        let rlat = lat / raddeg;
+        let r = (self.earth_radius_m * rlat.cos() / self.mesh_m)
+            * ((1.0 + tlat.sin()) / (1.0 + rlat.sin()));
+
+        let ang = (270.0 + stlon - lon) / raddeg;
+
+        Point::new(r * ang.cos() + self.pole_x, r * ang.sin() + self.pole_y)
+    }
+
+    // grid coordinates to polar stereographic metres from the pole, the projected coordinates of proj_string
+    pub fn to_meters(&self, x: f64, y: f64) -> Point {
+        Point::new(
+            (x - self.pole_x) * self.mesh_m,
+            (y - self.pole_y) * self.mesh_m,
+        )
+    }
+
+    pub fn from_meters(&self, x: f64, y: f64) -> Point {
+        Point::new(x / self.mesh_m + self.pole_x, y / self.mesh_m + self.pole_y)
+    }
+
+    // the ground length in metres of a grid cell side at a latitude, the mesh times the map scale factor
+    pub fn mesh_at(&self, lat: f64) -> f64 {
+        let raddeg = 180.0 / PI;
+        self.mesh_m * (1.0 + (lat / raddeg).sin()) / (1.0 + (self.true_latitude / raddeg).sin())
+    }
+
+    // projected coordinates are metres from the pole
+    pub fn proj_string(&self) -> String {
+        format!(
+            "+proj=stere +lat_0=90 +lat_ts={} +lon_0={} +k=1 +x_0=0 +y_0=0 +R={} +units=m +no_defs",
+            self.true_latitude, self.central_meridian, self.earth_radius_m
+        )
+    }
+
+    // OGC WKT 1, as written to .prj files
+    pub fn wkt(&self) -> String {
+        format!(
+            concat!(
+                "PROJCS[\"HRAP Polar Stereographic\",{},",
+                "PROJECTION[\"Polar_Stereographic\"],",
+                "PARAMETER[\"latitude_of_origin\",{}],",
+                "PARAMETER[\"central_meridian\",{}],",
+                "PARAMETER[\"scale_factor\",1],",
+                "PARAMETER[\"false_easting\",0],",
+                "PARAMETER[\"false_northing\",0],",
+                "UNIT[\"Meter\",1]]"
+            ),
+            self.geographic_wkt(),
+            self.true_latitude,
+            self.central_meridian
+        )
+    }
+
+    // the WKT of signed lat/lon on the HRAP sphere
+    pub fn geographic_wkt(&self) -> String {
+        format!(
+            concat!(
+                "GEOGCS[\"HRAP Sphere\",DATUM[\"D_Sphere\",SPHEROID[\"Sphere\",{},0]],",
+                "PRIMEM[\"Greenwich\",0],UNIT[\"Degree\",0.0174532925199433]]"
+            ),
+            self.earth_radius_m
+        )
+    }
+}
+
 // positive longitude values are West, Positive latitude North
-// derived from https://www.nws.noaa.gov/oh/hrl/dmip/lat_lon.txt
 pub fn hrap_to_latlon(x: f64, y: f64) -> Point {
-    let earthr = 6371.2;
-    let stlon = 105.0;
-    let raddeg = 180.0 / PI;
-    let xmesh = 4.7625;
-    let tlat = 60.0 / raddeg;
+    HrapProjection::default().inverse_positive_west(x, y)
+}
 
-    let _x = x - 401.0; // >
-    let _y = y - 1601.0; // >
+// the inverse of hrap_to_latlon, longitude is positive west
+pub fn latlon_to_hrap(lon: f64, lat: f64) -> Point {
+    HrapProjection::default().forward_positive_west(lon, lat)
+}
 
-    let rr = (_x * _x) + (_y * _y);
+// the ground length in km of an HRAP cell side at a latitude. The mesh is 4.7625 km at 60N and shrinks toward
+// the equator with the polar stereographic map scale factor, to about 3.6 km at 25N
+pub fn hrap_mesh_km(lat: f64) -> f64 {
+    HrapProjection::default().mesh_at(lat) / 1000.0
+}
 
-    let gi = (earthr * (1.0 + tlat.sin())) / xmesh;
-    let _gi = gi * gi;
+// the ground area in km2 of the HRAP cell centred on (x, y)
+pub fn hrap_cell_area_km2(x: f64, y: f64) -> f64 {
+    let mesh = hrap_mesh_km(hrap_to_latlon(x, y).y);
+    mesh * mesh
+}
 
-    let rlat = ((_gi - rr) / (_gi + rr)).asin() * raddeg;
+// hrap_to_latlon with a choice of longitude convention, exports use LonConvention::Signed
+pub fn hrap_to_latlon_with(x: f64, y: f64, lon: LonConvention) -> Point {
+    let point = hrap_to_latlon(x, y);
+    Point::new(lon.from_positive_west(point.x), point.y)
+}
 
-    let mut ang = _y.atan2(_x) * raddeg;
+// the inverse of hrap_to_latlon_with
+pub fn latlon_to_hrap_with(lon: f64, lat: f64, convention: LonConvention) -> Point {
+    match convention {
+        LonConvention::PositiveWest => latlon_to_hrap(lon, lat),
+        LonConvention::Signed => latlon_to_hrap(-lon, lat),
+    }
+}
 
-    // let if (ang.lt.0.) ang = ang + 360.0;
-    ang += if ang < 0.0 { 360.0 } else { 0.0 };
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LonConvention {
+    PositiveWest, // as returned by hrap_to_latlon, 106 for 106W
+    Signed,       // -180..180, negative west, as expected by GIS tools
+}
 
-    let mut rlon = 270.0 + stlon - ang;
+impl LonConvention {
+    // converts a positive west longitude from hrap_to_latlon to this convention
+    pub fn from_positive_west(&self, lon: f64) -> f64 {
+        match self {
+            LonConvention::PositiveWest => lon,
+            LonConvention::Signed => signed_longitude(lon),
+        }
+    }
+}
 
-    // let if(rlon.lt.0.) rlon=rlon+360.0;
-    rlon += if rlon < 0.0 { 360.0 } else { 0.0 };
+// how exports locate cells
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Coordinates {
+    LatLon(LonConvention),
+    Meters, // polar stereographic x and y, see HrapProjection::to_meters
+}
 
-    // let if(rlon.gt.360.0) rlon = rlon - 360.0;
-    rlon -= if rlon > 360.0 { 360.0 } else { 0.0 };
+impl Coordinates {
+    // the output location of an HRAP coordinate
+    pub fn locate(&self, x: f64, y: f64) -> Point {
+        match self {
+            Coordinates::LatLon(lon) => hrap_to_latlon_with(x, y, *lon),
+            Coordinates::Meters => HrapProjection::default().to_meters(x, y),
+        }
+    }
+}
 
-    Point::new(rlon, rlat)
+// converts the positive west longitudes of hrap_to_latlon to conventional -180..180 longitudes
+pub fn signed_longitude(positive_west: f64) -> f64 {
+    let lon = -positive_west;
+    if lon < -180.0 {
+        lon + 360.0
+    } else {
+        lon
+    }
 }
 
 #[cfg(test)]
@@ -53,6 +249,76 @@ mod tests {
         assert!(point.y > 33.0 && point.y < 34.0);
     }
 
+    #[test]
+    fn latlon_to_hrap_round_trip_test() {
+        let point = hrap_to_latlon(367.0, 263.0);
+        let hrap = latlon_to_hrap(point.x, point.y);
+
+        assert!((hrap.x - 367.0).abs() < 1e-9);
+        assert!((hrap.y - 263.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hrap_cell_area_test() {
+        assert!((hrap_mesh_km(60.0) - 4.7625).abs() < 1e-12);
+
+        // cells shrink to the south, under 16 km2 in west Texas against 22.7 km2 at 60N
+        let south = hrap_cell_area_km2(367.0, 263.0);
+        let north = hrap_cell_area_km2(700.0, 1000.0);
+        assert!(south > 15.5 && south < 16.0);
+        assert!(north > 22.6 && north < 22.7);
+    }
+
+    #[test]
+    fn lon_convention_test() {
+        let legacy = hrap_to_latlon_with(367.5, 263.5, LonConvention::PositiveWest);
+        let signed = hrap_to_latlon_with(367.5, 263.5, LonConvention::Signed);
+        assert!(legacy.x > 106.0 && legacy.x < 107.0);
+        assert_eq!(signed.x, -legacy.x);
+
+        // east of 180 in positive west terms, e.g. the far corner of a large grid, is east longitude
+        assert_eq!(LonConvention::Signed.from_positive_west(350.0), 10.0);
+
+        let hrap = latlon_to_hrap_with(signed.x, signed.y, LonConvention::Signed);
+        assert!((hrap.x - 367.5).abs() < 1e-9 && (hrap.y - 263.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn projection_test() {
+        let hrap = HrapProjection::default();
+        let point = hrap.inverse(367.5, 263.5);
+        assert_eq!(
+            point,
+            hrap_to_latlon_with(367.5, 263.5, LonConvention::Signed)
+        );
+
+        let grid = hrap.forward(point.x, point.y);
+        assert!((grid.x - 367.5).abs() < 1e-9 && (grid.y - 263.5).abs() < 1e-9);
+
+        // quarter HRAP coordinates are 4 times HRAP coordinates
+        let shrap = HrapProjection::subdivided(4).forward(point.x, point.y);
+        assert!((shrap.x - 1470.0).abs() < 1e-9 && (shrap.y - 1054.0).abs() < 1e-9);
+
+        let meters = hrap.to_meters(401.0, 1602.0);
+        assert_eq!(meters, Point::new(0.0, 4762.5));
+        assert_eq!(
+            hrap.from_meters(meters.x, meters.y),
+            Point::new(401.0, 1602.0)
+        );
+        let shrap = HrapProjection::subdivided(4);
+        assert_eq!(shrap.to_meters(1604.0, 6408.0), meters);
+
+        assert!(hrap.proj_string().contains("+lat_ts=60 +lon_0=-105"));
+        assert!(hrap.wkt().contains("PARAMETER[\"central_meridian\",-105]"));
+    }
+
+    #[test]
+    fn pole_and_meridian_test() {
+        // the pole, and 105W straight up the grid
+        assert!((hrap_to_latlon(401.0, 1601.0).y - 90.0).abs() < 1e-9);
+        assert!((hrap_to_latlon(401.0, 500.0).x - 105.0).abs() < 1e-9);
+    }
+
     // println!("long lat is {:?}", hrap_to_latlon(367.0, 263.0));
     // println!("other lat long is {:?}", hrap_to_latlon(367.0 + 335.0 , 263.0 + 159.0));
     // println!("other lat long max x, min y is {:?}", hrap_to_latlon(367.0 + 334.0 , 263.0));
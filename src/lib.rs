@@ -1,23 +1,44 @@
+pub mod accumulate;
+pub mod algebra;
+pub mod areal;
+pub mod basin;
+pub mod checksum;
+pub mod climatology;
+pub mod crop;
+pub mod datacard;
+pub mod datetime;
 pub mod endian;
 pub mod geo;
+pub mod geotiff;
+pub mod gzip;
 pub mod headers;
 pub mod hrap;
+pub mod json;
+pub mod mask;
+pub mod mosaic;
+pub mod netcdf;
+pub mod png;
 pub mod read_bytes;
+pub mod reader;
+pub mod reproject;
+pub mod shapefile;
+pub mod stack;
+pub mod station;
+pub mod terminal;
 pub mod utils;
+pub mod verify;
+pub mod volume;
+pub mod writer;
 pub mod xmrg_version;
 
-use std::fs::File;
-use std::io;
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::io::SeekFrom;
-
-use endian::get_endian;
+use datetime::DateSegments;
 use geo::Feature;
-use headers::{build_4_2_reader, Header, Metadata};
-use read_bytes::ReadBytes;
-use utils::to_mm;
-use xmrg_version::{get_xmrg_version, XmrgVersion};
+use headers::CellPosition;
+use headers::{Header, Metadata};
+use hrap::{latlon_to_hrap_with, Coordinates, LonConvention};
+use reader::XmrgReader;
+
+use std::io;
 
 // https://tgftp.nws.noaa.gov/data/rfc/wgrfc/
 // https://www.nws.noaa.gov/oh/hrl/dmip/2/xmrgformat.html
@@ -29,64 +50,9 @@ use xmrg_version::{get_xmrg_version, XmrgVersion};
 // HRAP https://www.nws.noaa.gov/oh/hrl/distmodel/hrap.htm
 // HRAP function https://www.nws.noaa.gov/oh/hrl/dmip/lat_lon.txt
 
-// const XOR: usize = 0;
-// const YOR: usize = 1;
-const COLUMNS: usize = 2;
-const ROWS: usize = 3;
-
-fn get_reader(path: &str) -> io::Result<BufReader<File>> {
-    let file = File::open(path)?;
-    Ok(BufReader::new(file))
-}
-
-fn process_row<R: Read + Seek>(read_bytes: ReadBytes, reader: &mut R) -> io::Result<Vec<f64>> {
-    reader.seek(SeekFrom::Current(4))?;
-
-    let result = read_bytes.iter(reader).map(|res| res.map(to_mm)).collect();
-
-    reader.seek(SeekFrom::Current(4))?;
-
-    result
-}
-
 // pub fn read_xmrg(path: &str) -> io::Result<Vec<Vec<f64>>> {
 pub fn read_xmrg(path: &str) -> io::Result<XmrgData> {
-    let mut reader = get_reader(path)?;
-    let endian = get_endian(&mut reader)?;
-
-    let header = ReadBytes::new(4, endian).read_int32s(&mut reader)?;
-    reader.seek(SeekFrom::Current(4))?;
-
-    let record_2_bytes = endian.read(&mut reader)?;
-
-    let xmrg_version = get_xmrg_version(record_2_bytes, header[COLUMNS]);
-
-    let row_reader = ReadBytes::new(header[COLUMNS], endian);
-
-    let header2: io::Result<Option<Metadata>> = match xmrg_version {
-        None => Ok(None),
-        Some(version) => {
-            reader.seek(SeekFrom::Start(24))?; // set reader to position just after header (4 bytes + 16 byte header + 4 bytes = 24)
-            match version {
-                XmrgVersion::Pre1997 => Ok(None),
-                XmrgVersion::Build4_2 => {
-                    let h2 = build_4_2_reader(&mut reader, endian)?;
-                    Ok(Some(Metadata::Header4_2(h2)))
-                }
-                XmrgVersion::Build5_2_2 => {
-                    reader.seek(SeekFrom::Current(42))?;
-                    Ok(None)
-                }
-            }
-        }
-    };
-
-    reader.seek(SeekFrom::Current(4))?; // seek past trailing i32
-    let values = (0..header[ROWS])
-        .map(|_| process_row(row_reader, &mut reader))
-        .collect::<io::Result<Vec<Vec<f64>>>>()?;
-
-    Ok(XmrgData::new(Header::from_vec(header), header2?, values))
+    XmrgReader::open(path)?.into_data()
 }
 
 pub struct XmrgData {
@@ -104,13 +70,40 @@ impl XmrgData {
         }
     }
 
+    pub fn valid_time(&self) -> Option<DateSegments> {
+        self.metadata
+            .as_ref()
+            .and_then(|m| m.valid_datetime())
+            .and_then(|dt| DateSegments::from_header_str(&dt))
+    }
+
+    // the value of the cell holding a point, longitude signed (negative west). MISSING outside of the grid
+    pub fn sample(&self, lon: f64, lat: f64) -> f64 {
+        reproject::nearest_at(self, latlon_to_hrap_with(lon, lat, LonConvention::Signed))
+    }
+
+    // interpolated between the four cell centres around a point, see reproject::bilinear_at
+    pub fn sample_bilinear(&self, lon: f64, lat: f64) -> f64 {
+        reproject::bilinear_at(self, latlon_to_hrap_with(lon, lat, LonConvention::Signed))
+    }
+
     // https://github.com/rust-lang/rfcs/blob/master/text/1951-expand-impl-trait.md#scoping-for-type-and-lifetime-parameters
     // pub fn generate_features<'a>(&'a self) -> impl Iterator<Item=Feature> + 'a {
+    // cell centres with signed longitudes, as GIS tools expect
     pub fn generate_features(&self) -> impl Iterator<Item = Feature> + '_ {
+        self.generate_features_with(Coordinates::LatLon(LonConvention::Signed))
+    }
+
+    // LonConvention::PositiveWest gives the longitudes of hrap_to_latlon, as older versions wrote, and
+    // Coordinates::Meters polar stereographic x and y
+    pub fn generate_features_with(
+        &self,
+        output: Coordinates,
+    ) -> impl Iterator<Item = Feature> + '_ {
         self.values
             .iter()
             .flat_map(|vec| vec.iter())
-            .zip(self.header.into_iter())
+            .zip(self.header.coordinates(CellPosition::Centre, output))
             .map(|(value, point)| Feature::new(point, *value))
     }
 }
@@ -1,17 +1,57 @@
 // use rexmrg::{ReadBytes, get_endian, get_reader, get_xmrg_version};
+use rexmrg::png::ColorRamp;
 use rexmrg::read_xmrg;
+use rexmrg::terminal::{render_heatmap, render_legend, summary};
+use rexmrg::verify::{read_gauges, verify, Sampling};
+use std::env;
 use std::f64;
 use std::fs::File;
 use std::io;
+use std::io::BufReader;
 use std::io::Read;
+use std::process;
+
+const USAGE: &str = "usage: rexmrg stats <xmrg>
+       rexmrg verify <xmrg> <gauges.csv> [--bilinear]
+       rexmrg view <xmrg> [--width <characters>]";
 
 // structop
-fn main() -> io::Result<()> {
-    println!("Hello, world!");
+// usage errors and other invalid input exit with status 2 and any other error with status 1, so scripts can
+// tell a failed run
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("rexmrg: {}", e);
+        process::exit(match e.kind() {
+            io::ErrorKind::InvalidInput => 2,
+            _ => 1,
+        });
+    }
+}
+
+fn run() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("stats") if args.len() == 3 => stats(&args[2]),
+        Some("verify") if args.len() >= 4 => verify_gauges(&args[2], &args[3], &args[4..]),
+        Some("view") if args.len() >= 3 => view(&args[2], &args[3..]),
+        Some(command @ ("stats" | "verify" | "view")) => {
+            Err(usage(&format!("wrong number of arguments for {}", command)))
+        }
+        Some(command) => Err(usage(&format!("unknown command {}", command))),
+        None => Err(usage("no command given")),
+    }
+}
+
+fn usage(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{}\n{}", message, USAGE),
+    )
+}
 
-    println!("------------ V1 --------------");
-    // ********************* start v1 ******************************
-    let xmrg_data = read_xmrg("xmrg0506199516z.gz").unwrap();
+fn stats(path: &str) -> io::Result<()> {
+    let xmrg_data = read_xmrg(path)?;
 
     let avg = average(&xmrg_data.values);
     let max = max(&xmrg_data.values);
@@ -19,14 +59,63 @@ fn main() -> io::Result<()> {
     println!("The avg is {}", avg);
     println!("The max is {}", max);
 
-    // ********************* end v1 ********************************
+    Ok(())
+}
+
+// per gauge table as CSV followed by the summary statistics
+fn verify_gauges(xmrg: &str, gauges: &str, options: &[String]) -> io::Result<()> {
+    let mut sampling = Sampling::Nearest;
+    for option in options {
+        match option.as_str() {
+            "--bilinear" => sampling = Sampling::Bilinear,
+            other => return Err(usage(&format!("unknown option {} for verify", other))),
+        }
+    }
+
+    let data = read_xmrg(xmrg)?;
+    let verification = verify(&data, &read_gauges(gauges)?, sampling);
+
+    println!("id,lon,lat,gauge,radar,difference");
+    for pair in verification.pairs.iter() {
+        println!("{}", pair.csv_row());
+    }
+    println!();
+    println!("{}", verification.summary());
+
+    Ok(())
+}
+
+// the header summary, the grid as coloured blocks and a legend, sized to COLUMNS unless a width is given
+fn view(path: &str, options: &[String]) -> io::Result<()> {
+    let mut width = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--width" => {
+                let value = options.next().map(String::as_str).unwrap_or_default();
+                match value.parse::<usize>() {
+                    Ok(w) if w > 0 => width = Some(w),
+                    _ => {
+                        return Err(usage(&format!(
+                            "--width needs a number of characters, not '{}'",
+                            value
+                        )))
+                    }
+                }
+            }
+            other => return Err(usage(&format!("unknown option {} for view", other))),
+        }
+    }
+    let width = width
+        .or_else(|| env::var("COLUMNS").ok().and_then(|w| w.parse().ok()))
+        .unwrap_or(80);
 
-    println!("---------- V2 ---------------");
-    // ********************* start v2 ******************************
+    let data = read_xmrg(path)?;
+    let ramp = ColorRamp::nws_precipitation();
 
-    // ********************* end v2 ********************************
-    println!("Fin ...");
-    // io::stdout().write_all(&row1_in_mm)?;
+    print!("{}", summary(&data));
+    print!("{}", render_heatmap(&data, width, &ramp));
+    print!("{}", render_legend(&ramp, width));
 
     Ok(())
 }
@@ -34,7 +123,7 @@ fn main() -> io::Result<()> {
 pub fn tester(path: &str, stop: usize) -> io::Result<()> {
     let file = File::open(path)?;
 
-    for (i, b) in file.bytes().enumerate() {
+    for (i, b) in BufReader::new(file).bytes().enumerate() {
         println!("byte {} is: {:b}", i, b.unwrap());
         if (i + 1) % 4 == 0 {
             println!("*********  INT!! *********");
@@ -48,7 +137,7 @@ pub fn tester(path: &str, stop: usize) -> io::Result<()> {
 }
 
 //this is gross, do something about it later
-pub fn average(data: &Vec<Vec<f64>>) -> f64 {
+pub fn average(data: &[Vec<f64>]) -> f64 {
     // let count = (data.len() * data[0].len()) as f64;
     let mut count = 0;
 
@@ -64,7 +153,7 @@ pub fn average(data: &Vec<Vec<f64>>) -> f64 {
         / (count as f64)
 }
 
-pub fn max(data: &Vec<Vec<f64>>) -> f64 {
+pub fn max(data: &[Vec<f64>]) -> f64 {
     data.iter()
         .flatten()
         .filter(|n| **n >= 0.0)
@@ -0,0 +1,356 @@
+use crate::areal::{BasinWeights, CellWeight};
+use crate::basin::Basin;
+use crate::geo::{Point, Polygon};
+use crate::headers::{Header, MAX_FACTOR, NATIONAL_X_END, NATIONAL_Y_END};
+use crate::hrap::{hrap_to_latlon_with, signed_longitude, LonConvention};
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::fs;
+use std::io;
+
+const MAX_X: i32 = NATIONAL_X_END * MAX_FACTOR;
+const MAX_Y: i32 = NATIONAL_Y_END * MAX_FACTOR;
+
+// a basin as a set of whole HRAP cells of one grid window, indexed rows x columns like XmrgData
+#[derive(Debug, Clone)]
+pub struct BasinMask {
+    pub id: String,
+    pub header: Header,
+    pub cells: Vec<Vec<bool>>,
+}
+
+// absolute HRAP cell coordinates of a basin, as listed in a cell list file
+#[derive(Debug, Clone)]
+pub struct CellList {
+    pub id: String,
+    pub cells: Vec<(i32, i32)>,
+}
+
+impl BasinMask {
+    // cells outside of the header window are ignored
+    pub fn from_cells(id: &str, header: Header, cells: &[(i32, i32)]) -> Self {
+        let mut mask = Self::empty(id, header);
+        for (x, y) in cells {
+            let column = x - header.xor();
+            let row = y - header.yor();
+            if (0..header.columns()).contains(&column) && (0..header.rows()).contains(&row) {
+                mask.cells[row as usize][column as usize] = true;
+            }
+        }
+        mask
+    }
+
+    pub fn from_cell_list(header: Header, list: &CellList) -> Self {
+        Self::from_cells(&list.id, header, &list.cells)
+    }
+
+    // a cell belongs to the basin when its centre is inside the basin polygons
+    pub fn from_basin(header: Header, basin: &Basin) -> Self {
+        let mut mask = Self::empty(&basin.id, header);
+        for (row, cells) in mask.cells.iter_mut().enumerate() {
+            for (column, cell) in cells.iter_mut().enumerate() {
+                let hrap = header.hrap_point(column, row);
+                *cell = basin.contains(hrap_to_latlon_with(hrap.x, hrap.y, LonConvention::Signed));
+            }
+        }
+        mask
+    }
+
+    fn empty(id: &str, header: Header) -> Self {
+        BasinMask {
+            id: String::from(id),
+            header,
+            cells: vec![vec![false; header.columns() as usize]; header.rows() as usize],
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.cells.iter().flatten().filter(|c| **c).count()
+    }
+
+    pub fn hrap_cells(&self) -> Vec<(i32, i32)> {
+        self.indices()
+            .map(|(row, column)| {
+                (
+                    self.header.xor() + column as i32,
+                    self.header.yor() + row as i32,
+                )
+            })
+            .collect()
+    }
+
+    pub fn to_cell_list(&self) -> CellList {
+        CellList {
+            id: self.id.clone(),
+            cells: self.hrap_cells(),
+        }
+    }
+
+    // every cell weighted equally, for use in a WeightTable
+    pub fn weights(&self) -> BasinWeights {
+        BasinWeights {
+            id: self.id.clone(),
+            cells: self
+                .indices()
+                .map(|(row, column)| CellWeight {
+                    row,
+                    column,
+                    weight: 1.0,
+                })
+                .collect(),
+        }
+    }
+
+    // the mean of the basin cells with data, MISSING if none have data
+    pub fn average(&self, data: &XmrgData) -> io::Result<f64> {
+        self.check_header(data)?;
+        Ok(self.weights().average(&data.values).0)
+    }
+
+    // a copy of the grid with every cell outside of the basin set to MISSING
+    pub fn clip(&self, data: &XmrgData) -> io::Result<XmrgData> {
+        self.check_header(data)?;
+
+        let values = data
+            .values
+            .iter()
+            .zip(self.cells.iter())
+            .map(|(values, cells)| {
+                values
+                    .iter()
+                    .zip(cells.iter())
+                    .map(|(value, inside)| if *inside { *value } else { MISSING })
+                    .collect()
+            })
+            .collect();
+
+        Ok(XmrgData::new(data.header, None, values))
+    }
+
+    // the number of basin cells with a value of at least threshold
+    pub fn count_at_least(&self, data: &XmrgData, threshold: f64) -> io::Result<usize> {
+        self.check_header(data)?;
+
+        Ok(self
+            .indices()
+            .filter(|(row, column)| {
+                let value = data.values[*row][*column];
+                value >= 0.0 && value >= threshold
+            })
+            .count())
+    }
+
+    fn indices(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.cells.iter().enumerate().flat_map(|(row, cells)| {
+            cells
+                .iter()
+                .enumerate()
+                .filter(|(_, inside)| **inside)
+                .map(move |(column, _)| (row, column))
+        })
+    }
+
+    pub(crate) fn check_header(&self, data: &XmrgData) -> io::Result<()> {
+        if data.header == self.header {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "grid header {:?} does not match the header of basin {}",
+                    data.header, self.id
+                ),
+            ))
+        }
+    }
+}
+
+pub fn read_cell_list(path: &str) -> io::Result<Vec<CellList>> {
+    parse_cell_list(&fs::read_to_string(path)?, path)
+}
+
+// lines of "x y" or "id x y" integer HRAP cell coordinates, any further columns (e.g. a value) after an id
+// are ignored. Ids may be numeric, like USGS gauge numbers, so a line is told apart by its number of columns
+// and not by its first one. Lines without an id belong to default_id, lines starting with # or $ are comments
+pub fn parse_cell_list(text: &str, default_id: &str) -> io::Result<Vec<CellList>> {
+    let mut lists: Vec<CellList> = Vec::new();
+
+    for (number, line) in data_lines(text) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (id, cell) = match tokens[..] {
+            [x, y] => (
+                default_id,
+                (parse_hrap(x, MAX_X, number)?, parse_hrap(y, MAX_Y, number)?),
+            ),
+            [id, x, y, ..] => (
+                id,
+                (parse_hrap(x, MAX_X, number)?, parse_hrap(y, MAX_Y, number)?),
+            ),
+            _ => return Err(invalid_line(number, "expected an HRAP x and y")),
+        };
+
+        match lists.iter_mut().find(|l| l.id == id) {
+            Some(list) => list.cells.push(cell),
+            None => lists.push(CellList {
+                id: String::from(id),
+                cells: vec![cell],
+            }),
+        }
+    }
+
+    Ok(lists)
+}
+
+pub fn read_basin_boundaries(path: &str) -> io::Result<Vec<Basin>> {
+    parse_basin_boundaries(&fs::read_to_string(path)?)
+}
+
+// NWSRFS style basin boundary files: a line "id [description] n" followed by n lines of "lat lon",
+// where longitudes are positive west as in hrap_to_latlon. Basins are returned with signed longitudes
+pub fn parse_basin_boundaries(text: &str) -> io::Result<Vec<Basin>> {
+    let mut lines = data_lines(text);
+    let mut basins = Vec::new();
+
+    while let Some((number, line)) = lines.next() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let count: usize = match tokens[..] {
+            [_, .., n] if tokens.len() > 1 => n
+                .parse()
+                .map_err(|_| invalid_line(number, "expected a point count"))?,
+            _ => return Err(invalid_line(number, "expected a basin id and point count")),
+        };
+
+        let ring = (0..count)
+            .map(|_| {
+                let (number, line) = lines
+                    .next()
+                    .ok_or_else(|| invalid_line(number, "basin has too few points"))?;
+                let numbers: Vec<f64> = line
+                    .split_whitespace()
+                    .map(|n| n.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| invalid_line(number, "expected a lat and lon"))?;
+                match numbers[..] {
+                    [lat, lon, ..] => Ok(Point::new(signed_longitude(lon), lat)),
+                    _ => Err(invalid_line(number, "expected a lat and lon")),
+                }
+            })
+            .collect::<io::Result<Vec<Point>>>()?;
+
+        basins.push(Basin::new(
+            String::from(tokens[0]),
+            vec![Polygon::new(ring, vec![])],
+        ));
+    }
+
+    Ok(basins)
+}
+
+fn data_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#') && !line.starts_with('$'))
+}
+
+// a whole cell coordinate inside the national grid at the finest subdivision, so that a misplaced id or value
+// column is an error rather than a cell silently dropped from the basin
+fn parse_hrap(token: &str, max: i32, line: usize) -> io::Result<i32> {
+    match token.parse::<i32>() {
+        Ok(n) if (0..=max).contains(&n) => Ok(n),
+        _ => Err(invalid_line(
+            line,
+            &format!("{} is not an HRAP cell coordinate from 0 to {}", token, max),
+        )),
+    }
+}
+
+fn invalid_line(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hrap::hrap_to_latlon;
+
+    #[test]
+    fn cell_list_mask_test() {
+        let text = "# basin cells\nB1 10 20\nB1 11 20 0.5\nB2 12 21\n50 50\n";
+        let lists = parse_cell_list(text, "default").unwrap();
+        assert_eq!(lists.len(), 3);
+        assert_eq!(lists[2].id, "default");
+
+        let header = Header::new(10, 20, 3, 2);
+        let mask = BasinMask::from_cell_list(header, &lists[0]);
+        assert_eq!(mask.count(), 2);
+        assert_eq!(mask.hrap_cells(), vec![(10, 20), (11, 20)]);
+
+        let data = XmrgData::new(header, None, vec![vec![1.0, MISSING, 5.0], vec![2.0; 3]]);
+        assert_eq!(mask.average(&data).unwrap(), 1.0);
+        assert_eq!(mask.count_at_least(&data, 0.5).unwrap(), 1);
+        assert_eq!(mask.clip(&data).unwrap().values[0][2], MISSING);
+    }
+
+    #[test]
+    fn numeric_cell_list_id_test() {
+        let lists = parse_cell_list(
+            "07196500 412 230
+07196500 413 230 1.5
+",
+            "default",
+        )
+        .unwrap();
+        assert_eq!(lists.len(), 1);
+        assert_eq!(lists[0].id, "07196500");
+        assert_eq!(lists[0].cells, vec![(412, 230), (413, 230)]);
+
+        // a value column without an id, or a coordinate off the grid
+        assert!(parse_cell_list(
+            "412 230 0.5
+",
+            "default"
+        )
+        .is_err());
+        assert!(parse_cell_list(
+            "B1 7196500 412
+",
+            "default"
+        )
+        .is_err());
+        assert!(parse_cell_list(
+            "412
+", "default"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn basin_boundary_mask_test() {
+        let header = Header::new(367, 263, 4, 4);
+        let corner = |column: usize, row: usize| {
+            let hrap = header.hrap_point(column, row);
+            let p = hrap_to_latlon(hrap.x - 0.5, hrap.y - 0.5);
+            format!("{} {}\n", p.y, p.x)
+        };
+        // the outline of the lower left 2 x 2 cells
+        let text = format!(
+            "ABC Basin ABC 4\n{}{}{}{}",
+            corner(0, 0),
+            corner(2, 0),
+            corner(2, 2),
+            corner(0, 2)
+        );
+
+        let basins = parse_basin_boundaries(&text).unwrap();
+        assert_eq!(basins[0].id, "ABC");
+
+        let mask = BasinMask::from_basin(header, &basins[0]);
+        assert_eq!(mask.count(), 4);
+        assert!(mask.cells[1][1] && !mask.cells[2][2]);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::headers::Metadata;
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::io;
+
+// combines grids on different HRAP windows, e.g. several RFCs, into one grid covering all of them.
+// Cells no grid has data for are MISSING
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Overlap {
+    First,                // the first grid, in the order given, with data
+    Max,                  // the largest value
+    Mean,                 // the mean of the grids with data
+    Priority(Vec<usize>), // indices of grids from most to least preferred, unlisted grids follow in order
+}
+
+pub fn mosaic(grids: &[XmrgData], overlap: &Overlap) -> io::Result<XmrgData> {
+    let first = grids
+        .first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no grids to mosaic"))?;
+    let header = grids[1..]
+        .iter()
+        .try_fold(first.header, |union, grid| union.union(&grid.header))?;
+
+    let order = preference(grids.len(), overlap)?;
+
+    let values: Vec<Vec<f64>> = (0..header.rows() as usize)
+        .map(|row| {
+            (0..header.columns() as usize)
+                .map(|column| {
+                    let hrap = header.hrap_point(column, row);
+                    let mut found = order.iter().filter_map(|i| grids[*i].value_at(hrap));
+                    let value = match overlap {
+                        Overlap::First | Overlap::Priority(_) => found.next(),
+                        Overlap::Max => found.reduce(f64::max),
+                        Overlap::Mean => {
+                            let (sum, count) =
+                                found.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+                            if count > 0 {
+                                Some(sum / f64::from(count))
+                            } else {
+                                None
+                            }
+                        }
+                    };
+                    value.unwrap_or(MISSING)
+                })
+                .collect()
+        })
+        .collect();
+
+    let metadata = common_metadata(grids, &values);
+    Ok(XmrgData::new(header, metadata, values))
+}
+
+fn preference(count: usize, overlap: &Overlap) -> io::Result<Vec<usize>> {
+    let mut order = match overlap {
+        Overlap::Priority(priority) => priority.clone(),
+        _ => Vec::new(),
+    };
+
+    if let Some(i) = order.iter().find(|i| **i >= count) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("priority index {} is not one of the {} grids", i, count),
+        ));
+    }
+
+    order.extend(
+        (0..count)
+            .filter(|i| !order.contains(i))
+            .collect::<Vec<usize>>(),
+    );
+    Ok(order)
+}
+
+// a mosaic of grids that are all valid at the same time is valid at that time too
+fn common_metadata(grids: &[XmrgData], values: &[Vec<f64>]) -> Option<Metadata> {
+    let time = grids[0].valid_time()?;
+    if grids.iter().all(|g| g.valid_time() == Some(time)) {
+        Some(Metadata::for_product(time, "MOSAIC", values))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+
+    #[test]
+    fn mosaic_test() {
+        let west = XmrgData::new(Header::new(10, 20, 2, 1), None, vec![vec![1.0, 4.0]]);
+        let east = XmrgData::new(Header::new(11, 21, 2, 1), None, vec![vec![2.0, 3.0]]);
+        let middle = XmrgData::new(
+            Header::new(11, 20, 1, 2),
+            None,
+            vec![vec![2.0], vec![MISSING]],
+        );
+        let grids = [west, east, middle];
+
+        let first = mosaic(&grids, &Overlap::First).unwrap();
+        assert_eq!(first.header, Header::new(10, 20, 3, 2));
+        assert_eq!(
+            first.values,
+            vec![vec![1.0, 4.0, MISSING], vec![MISSING, 2.0, 3.0]]
+        );
+
+        let max = mosaic(&grids, &Overlap::Max).unwrap();
+        assert_eq!(max.values[0][1], 4.0);
+
+        let mean = mosaic(&grids, &Overlap::Mean).unwrap();
+        assert_eq!(mean.values[0][1], 3.0);
+
+        let priority = mosaic(&grids, &Overlap::Priority(vec![2])).unwrap();
+        assert_eq!(priority.values[0][1], 2.0);
+        // the preferred grid is missing there, the next one is used
+        assert_eq!(priority.values[1][1], 2.0);
+
+        assert!(mosaic(&grids, &Overlap::Priority(vec![3])).is_err());
+        assert!(mosaic(&[], &Overlap::First).is_err());
+
+        // a SHRAP window does not line up with HRAP ones
+        let shrap = XmrgData::new(
+            Header::new(40, 80, 1, 1).with_factor(4).unwrap(),
+            None,
+            vec![vec![1.0]],
+        );
+        let hrap = XmrgData::new(Header::new(10, 20, 2, 1), None, vec![vec![1.0, 4.0]]);
+        let mixed = [hrap, shrap];
+        let error = mosaic(&mixed, &Overlap::First).err();
+        assert_eq!(error.map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+}
@@ -0,0 +1,480 @@
+use crate::datetime::DateSegments;
+use crate::headers::{Header, Metadata, OperSys};
+use crate::hrap::{hrap_to_latlon_with, HrapProjection, LonConvention};
+use crate::stack::GridStack;
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+// CF conventions NetCDF in the classic (CDF-1) format, every variable is big endian and fixed size.
+// dimensions are time, y and x with y south to north like the rows of XmrgData
+
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+
+const NC_CHAR: u32 = 2;
+const NC_INT: u32 = 4;
+const NC_FLOAT: u32 = 5;
+const NC_DOUBLE: u32 = 6;
+
+const TIME: u32 = 0;
+const Y: u32 = 1;
+const X: u32 = 2;
+
+enum Attr {
+    Text(String),
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+struct Var {
+    name: &'static str,
+    dims: Vec<u32>,
+    attrs: Vec<(&'static str, Attr)>,
+    nc_type: u32,
+    data: Vec<u8>,
+}
+
+// the units of the x and y coordinate variables
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GridUnits {
+    Hrap,   // HRAP cell coordinates of cell centres
+    Meters, // polar stereographic metres, CF projection coordinates
+}
+
+// a single grid, with a time dimension of one at its valid time
+pub fn write_netcdf(path: &str, data: &XmrgData, units: GridUnits) -> io::Result<()> {
+    let time = data.valid_time().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the grid has no valid time, put it in a GridStack to give it one",
+        )
+    })?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_netcdf_to(
+        &mut writer,
+        data.header,
+        data.metadata.as_ref(),
+        &[time],
+        &[&data.values],
+        units,
+    )?;
+    writer.flush()
+}
+
+pub fn write_netcdf_stack(path: &str, stack: &GridStack, units: GridUnits) -> io::Result<()> {
+    let grids: Vec<&Vec<Vec<f64>>> = stack.values.iter().collect();
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_netcdf_to(&mut writer, stack.header, None, &stack.times, &grids, units)?;
+    writer.flush()
+}
+
+pub fn write_netcdf_to<W: Write>(
+    writer: &mut W,
+    header: Header,
+    metadata: Option<&Metadata>,
+    times: &[DateSegments],
+    grids: &[&Vec<Vec<f64>>],
+    units: GridUnits,
+) -> io::Result<()> {
+    // a time dimension of length 0 would be read as the record dimension
+    if grids.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot write a NetCDF file with no grids",
+        ));
+    }
+
+    if times.len() != grids.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} times for {} grids", times.len(), grids.len()),
+        ));
+    }
+
+    let columns = header.columns() as usize;
+    let rows = header.rows() as usize;
+
+    // the hrap_ attributes of the grid mapping relate HRAP coordinates to projected metres
+    let projection = header.projection();
+    let hrap = HrapProjection::default();
+
+    let (y_attrs, y_data) = coordinate_var(
+        "y",
+        (0..rows).map(|row| header.hrap_point(0, row).y),
+        hrap.pole_y,
+        hrap.mesh_m,
+        units,
+    );
+    let (x_attrs, x_data) = coordinate_var(
+        "x",
+        (0..columns).map(|column| header.hrap_point(column, 0).x),
+        hrap.pole_x,
+        hrap.mesh_m,
+        units,
+    );
+
+    let latlon: Vec<_> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            let hrap = header.hrap_point(column, row);
+            hrap_to_latlon_with(hrap.x, hrap.y, LonConvention::Signed)
+        })
+        .collect();
+
+    let vars = vec![
+        Var {
+            name: "time",
+            dims: vec![TIME],
+            attrs: vec![
+                ("standard_name", Attr::Text(String::from("time"))),
+                (
+                    "units",
+                    Attr::Text(String::from("hours since 1970-01-01 00:00:00")),
+                ),
+                ("calendar", Attr::Text(String::from("standard"))),
+            ],
+            nc_type: NC_DOUBLE,
+            data: doubles(times.iter().map(|t| t.hours_since_epoch() as f64)),
+        },
+        Var {
+            name: "y",
+            dims: vec![Y],
+            attrs: y_attrs,
+            nc_type: NC_DOUBLE,
+            data: y_data,
+        },
+        Var {
+            name: "x",
+            dims: vec![X],
+            attrs: x_attrs,
+            nc_type: NC_DOUBLE,
+            data: x_data,
+        },
+        Var {
+            name: "lat",
+            dims: vec![Y, X],
+            attrs: vec![
+                ("standard_name", Attr::Text(String::from("latitude"))),
+                ("units", Attr::Text(String::from("degrees_north"))),
+            ],
+            nc_type: NC_FLOAT,
+            data: floats(latlon.iter().map(|p| p.y)),
+        },
+        Var {
+            name: "lon",
+            dims: vec![Y, X],
+            attrs: vec![
+                ("standard_name", Attr::Text(String::from("longitude"))),
+                ("units", Attr::Text(String::from("degrees_east"))),
+            ],
+            nc_type: NC_FLOAT,
+            data: floats(latlon.iter().map(|p| p.x)),
+        },
+        Var {
+            name: "hrap",
+            dims: vec![],
+            attrs: vec![
+                (
+                    "grid_mapping_name",
+                    Attr::Text(String::from("polar_stereographic")),
+                ),
+                (
+                    "straight_vertical_longitude_from_pole",
+                    Attr::Double(vec![projection.central_meridian]),
+                ),
+                (
+                    "standard_parallel",
+                    Attr::Double(vec![projection.true_latitude]),
+                ),
+                ("latitude_of_projection_origin", Attr::Double(vec![90.0])),
+                ("false_easting", Attr::Double(vec![0.0])),
+                ("false_northing", Attr::Double(vec![0.0])),
+                (
+                    "earth_radius",
+                    Attr::Double(vec![projection.earth_radius_m]),
+                ),
+                ("crs_wkt", Attr::Text(projection.wkt())),
+                ("proj4_params", Attr::Text(projection.proj_string())),
+                ("hrap_mesh_length", Attr::Double(vec![hrap.mesh_m])),
+                ("hrap_pole_x", Attr::Double(vec![hrap.pole_x])),
+                ("hrap_pole_y", Attr::Double(vec![hrap.pole_y])),
+                ("hrap_subdivision", Attr::Int(vec![header.factor()])),
+            ],
+            nc_type: NC_INT,
+            data: 0i32.to_be_bytes().to_vec(),
+        },
+        Var {
+            name: "precipitation",
+            dims: vec![TIME, Y, X],
+            // stacks may hold hourly grids, longer totals or mosaics, so the period is not named here. Each
+            // value is the total over the period ending at its time
+            attrs: vec![
+                ("long_name", Attr::Text(String::from("precipitation"))),
+                ("cell_methods", Attr::Text(String::from("time: sum"))),
+                ("units", Attr::Text(String::from("mm"))),
+                ("_FillValue", Attr::Float(vec![MISSING as f32])),
+                ("grid_mapping", Attr::Text(String::from("hrap"))),
+                ("coordinates", Attr::Text(String::from("lat lon"))),
+            ],
+            nc_type: NC_FLOAT,
+            data: floats(
+                grids
+                    .iter()
+                    .flat_map(|grid| grid.iter().flatten())
+                    .map(|v| if *v < 0.0 { MISSING } else { *v }),
+            ),
+        },
+    ];
+
+    let dims = [
+        ("time", times.len() as u32),
+        ("y", rows as u32),
+        ("x", columns as u32),
+    ];
+    let attrs = global_attributes(header, metadata);
+
+    // the header size does not depend on the variable offsets, so write it once to measure it
+    let header_len = file_header(&dims, &attrs, &vars, &vec![0; vars.len()]).len();
+    let mut begins = Vec::new();
+    let mut offset = header_len;
+    for var in vars.iter() {
+        begins.push(u32::try_from(offset).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "grids are too large for the classic netcdf format",
+            )
+        })?);
+        offset += padded(var.data.len());
+    }
+
+    writer.write_all(&file_header(&dims, &attrs, &vars, &begins))?;
+    for var in vars.iter() {
+        writer.write_all(&var.data)?;
+        writer.write_all(&vec![0; padded(var.data.len()) - var.data.len()])?;
+    }
+    Ok(())
+}
+
+fn global_attributes(header: Header, metadata: Option<&Metadata>) -> Vec<(&'static str, Attr)> {
+    let mut attrs = vec![
+        ("Conventions", Attr::Text(String::from("CF-1.6"))),
+        ("title", Attr::Text(String::from("XMRG precipitation"))),
+        ("source", Attr::Text(String::from("rexmrg"))),
+        ("xmrg_xor", Attr::Int(vec![header.xor()])),
+        ("xmrg_yor", Attr::Int(vec![header.yor()])),
+        ("xmrg_columns", Attr::Int(vec![header.columns()])),
+        ("xmrg_rows", Attr::Int(vec![header.rows()])),
+    ];
+
+    if let Some(m) = metadata {
+        let text = |s: String| {
+            Attr::Text(String::from(
+                s.trim_matches(|c: char| c == '\0' || c == ' '),
+            ))
+        };
+        attrs.push(("xmrg_saved_datetime", text(m.datetime())));
+        if let Some(user_id) = m.user_id() {
+            attrs.push(("xmrg_user_id", text(user_id)));
+        }
+        if let Some(flag) = m.process_flag() {
+            attrs.push(("xmrg_process_flag", text(flag)));
+        }
+        if let Some(valid) = m.valid_datetime() {
+            attrs.push(("xmrg_valid_datetime", text(valid)));
+        }
+        if let Some(max) = m.max_value() {
+            attrs.push(("xmrg_max_value", Attr::Int(vec![max])));
+        }
+        if let Some(version) = m.version() {
+            attrs.push(("xmrg_version", Attr::Float(vec![version])));
+        }
+        if let Some(os) = m.os() {
+            let os = match os {
+                OperSys::HP => "HP",
+                OperSys::LX => "LX",
+                OperSys::Unknown => "unknown",
+            };
+            attrs.push(("xmrg_operating_system", Attr::Text(String::from(os))));
+        }
+    }
+
+    attrs
+}
+
+fn file_header(
+    dims: &[(&str, u32)],
+    attrs: &[(&str, Attr)],
+    vars: &[Var],
+    begins: &[u32],
+) -> Vec<u8> {
+    let mut bytes = b"CDF\x01".to_vec();
+    bytes.extend(&0u32.to_be_bytes()); // no record variables
+
+    bytes.extend(&NC_DIMENSION.to_be_bytes());
+    bytes.extend(&(dims.len() as u32).to_be_bytes());
+    for (name, len) in dims {
+        bytes.extend(nc_name(name));
+        bytes.extend(&len.to_be_bytes());
+    }
+
+    bytes.extend(attribute_list(attrs));
+
+    bytes.extend(&NC_VARIABLE.to_be_bytes());
+    bytes.extend(&(vars.len() as u32).to_be_bytes());
+    for (var, begin) in vars.iter().zip(begins.iter()) {
+        bytes.extend(nc_name(var.name));
+        bytes.extend(&(var.dims.len() as u32).to_be_bytes());
+        for dim in var.dims.iter() {
+            bytes.extend(&dim.to_be_bytes());
+        }
+        bytes.extend(attribute_list(&var.attrs));
+        bytes.extend(&var.nc_type.to_be_bytes());
+        bytes.extend(&(padded(var.data.len()) as u32).to_be_bytes());
+        bytes.extend(&begin.to_be_bytes());
+    }
+
+    bytes
+}
+
+fn attribute_list(attrs: &[(&str, Attr)]) -> Vec<u8> {
+    if attrs.is_empty() {
+        return vec![0; 8];
+    }
+
+    let mut bytes = NC_ATTRIBUTE.to_be_bytes().to_vec();
+    bytes.extend(&(attrs.len() as u32).to_be_bytes());
+    for (name, attr) in attrs {
+        bytes.extend(nc_name(name));
+        let (nc_type, count, values) = match attr {
+            Attr::Text(s) => (NC_CHAR, s.len(), s.as_bytes().to_vec()),
+            Attr::Int(v) => (
+                NC_INT,
+                v.len(),
+                v.iter().flat_map(|n| n.to_be_bytes()).collect(),
+            ),
+            Attr::Float(v) => (NC_FLOAT, v.len(), floats(v.iter().map(|n| f64::from(*n)))),
+            Attr::Double(v) => (NC_DOUBLE, v.len(), doubles(v.iter().copied())),
+        };
+        bytes.extend(&nc_type.to_be_bytes());
+        bytes.extend(&(count as u32).to_be_bytes());
+        bytes.extend(pad(values));
+    }
+    bytes
+}
+
+fn nc_name(name: &str) -> Vec<u8> {
+    let mut bytes = (name.len() as u32).to_be_bytes().to_vec();
+    bytes.extend(pad(name.as_bytes().to_vec()));
+    bytes
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(4) * 4
+}
+
+fn pad(mut bytes: Vec<u8>) -> Vec<u8> {
+    bytes.resize(padded(bytes.len()), 0);
+    bytes
+}
+
+fn doubles(values: impl Iterator<Item = f64>) -> Vec<u8> {
+    values.flat_map(|v| v.to_be_bytes()).collect()
+}
+
+// an x or y coordinate variable from the HRAP coordinates of the cell centres along it
+fn coordinate_var(
+    axis: &str,
+    hrap: impl Iterator<Item = f64>,
+    pole: f64,
+    mesh_m: f64,
+    units: GridUnits,
+) -> (Vec<(&'static str, Attr)>, Vec<u8>) {
+    let (name, unit, data) = match units {
+        GridUnits::Hrap => (
+            ("long_name", format!("HRAP {} coordinate", axis)),
+            "1",
+            doubles(hrap),
+        ),
+        GridUnits::Meters => (
+            ("standard_name", format!("projection_{}_coordinate", axis)),
+            "m",
+            doubles(hrap.map(|v| (v - pole) * mesh_m)),
+        ),
+    };
+
+    let attrs = vec![
+        (name.0, Attr::Text(name.1)),
+        ("units", Attr::Text(String::from(unit))),
+        ("axis", Attr::Text(axis.to_uppercase())),
+    ];
+    (attrs, data)
+}
+
+fn floats(values: impl Iterator<Item = f64>) -> Vec<u8> {
+    values.flat_map(|v| (v as f32).to_be_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_netcdf_test() {
+        let header = Header::new(367, 263, 3, 2);
+        let grid = vec![vec![1.0, 2.0, MISSING], vec![4.0, 5.0, 6.0]];
+        let time = DateSegments::new(2020, 1, 1, 1);
+
+        let mut bytes = Vec::new();
+        write_netcdf_to(&mut bytes, header, None, &[time], &[&grid], GridUnits::Hrap).unwrap();
+
+        assert_eq!(&bytes[..4], b"CDF\x01");
+
+        // precipitation is the last variable, so its data ends the file
+        let n = bytes.len();
+        let last = f32::from_be_bytes([bytes[n - 4], bytes[n - 3], bytes[n - 2], bytes[n - 1]]);
+        let missing =
+            f32::from_be_bytes([bytes[n - 16], bytes[n - 15], bytes[n - 14], bytes[n - 13]]);
+        assert_eq!(last, 6.0);
+        assert_eq!(missing, -999.0);
+
+        // 8 byte time + 16 byte y + 24 byte x + 2 * 24 byte lat/lon + 4 byte hrap + 24 byte precipitation
+        let data_len = 8 + 16 + 24 + 24 + 24 + 4 + 24;
+        let header_len = n - data_len;
+        let begin_at = header_len - 4;
+        let begin = u32::from_be_bytes([
+            bytes[begin_at],
+            bytes[begin_at + 1],
+            bytes[begin_at + 2],
+            bytes[begin_at + 3],
+        ]);
+        assert_eq!(begin as usize, n - 24);
+        let attrs = &bytes[..header_len];
+        assert!(attrs.windows(9).any(|w| w == b"time: sum"));
+
+        let empty = write_netcdf_to(&mut Vec::new(), header, None, &[], &[], GridUnits::Hrap);
+        assert_eq!(empty.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        // metre coordinates only change attributes and the x and y values
+        let mut meters = Vec::new();
+        write_netcdf_to(
+            &mut meters,
+            header,
+            None,
+            &[time],
+            &[&grid],
+            GridUnits::Meters,
+        )
+        .unwrap();
+        assert_eq!(meters[meters.len() - 24..], bytes[n - 24..]);
+    }
+}
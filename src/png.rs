@@ -0,0 +1,366 @@
+use crate::checksum::{adler32, Crc32};
+use crate::XmrgData;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+// quick look RGBA PNG images, north up, one or more pixels per cell. Missing cells and cells below the first
+// break of the colour ramp are transparent. The image data is zlib compressed with stored (uncompressed) deflate
+// blocks, which every PNG reader accepts and needs no compressor
+
+const MM_PER_INCH: f64 = 25.4;
+
+// the NWS precipitation colours, with their breaks in inches
+const NWS_PRECIPITATION: [(f64, [u8; 3]); 15] = [
+    (0.01, [0x04, 0xe9, 0xe7]),
+    (0.10, [0x01, 0x9f, 0xf4]),
+    (0.25, [0x03, 0x00, 0xf4]),
+    (0.50, [0x02, 0xfd, 0x02]),
+    (0.75, [0x01, 0xc5, 0x01]),
+    (1.00, [0x00, 0x8e, 0x00]),
+    (1.50, [0xfd, 0xf8, 0x02]),
+    (2.00, [0xe5, 0xbc, 0x00]),
+    (2.50, [0xfd, 0x95, 0x00]),
+    (3.00, [0xfd, 0x00, 0x00]),
+    (4.00, [0xd4, 0x00, 0x00]),
+    (5.00, [0xbc, 0x00, 0x00]),
+    (6.00, [0xf8, 0x00, 0xfd]),
+    (8.00, [0x98, 0x54, 0xc6]),
+    (10.00, [0xfd, 0xfd, 0xfd]),
+];
+
+// a value takes the colour of the highest break it reaches, breaks are in mm and ascending
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRamp {
+    pub breaks: Vec<(f64, [u8; 3])>,
+}
+
+impl ColorRamp {
+    pub fn new(mut breaks: Vec<(f64, [u8; 3])>) -> Self {
+        breaks.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        ColorRamp { breaks }
+    }
+
+    pub fn nws_precipitation() -> Self {
+        Self::new(
+            NWS_PRECIPITATION
+                .iter()
+                .map(|(inches, rgb)| (inches * MM_PER_INCH, *rgb))
+                .collect(),
+        )
+    }
+
+    // the same colours at other breaks, one break for each colour or an InvalidInput error
+    pub fn with_breaks(&self, breaks: &[f64]) -> io::Result<Self> {
+        if breaks.len() != self.breaks.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} breaks given for a ramp of {} colours",
+                    breaks.len(),
+                    self.breaks.len()
+                ),
+            ));
+        }
+
+        Ok(Self::new(
+            breaks
+                .iter()
+                .zip(self.breaks.iter())
+                .map(|(value, (_, rgb))| (*value, *rgb))
+                .collect(),
+        ))
+    }
+
+    // None for missing values and values below the first break
+    pub fn color(&self, value: f64) -> Option<[u8; 3]> {
+        if value < 0.0 {
+            return None;
+        }
+        self.breaks
+            .iter()
+            .take_while(|(at, _)| value >= *at)
+            .last()
+            .map(|(_, rgb)| *rgb)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngOptions {
+    pub ramp: ColorRamp,
+    pub legend: bool,
+    pub cell_pixels: usize,
+}
+
+impl PngOptions {
+    pub fn new() -> Self {
+        PngOptions {
+            ramp: ColorRamp::nws_precipitation(),
+            legend: true,
+            cell_pixels: 1,
+        }
+    }
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn write_png(path: &str, data: &XmrgData, options: &PngOptions) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_png_to(&mut writer, data, options)?;
+    writer.flush()
+}
+
+pub fn write_png_to<W: Write>(
+    writer: &mut W,
+    data: &XmrgData,
+    options: &PngOptions,
+) -> io::Result<()> {
+    let rows = data.values.len();
+    let columns = data.values.first().map_or(0, |row| row.len());
+    if rows == 0 || columns == 0 || options.cell_pixels == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot draw an empty grid as a PNG",
+        ));
+    }
+
+    let map_width = columns * options.cell_pixels;
+    let map_height = rows * options.cell_pixels;
+    let legend = if options.legend {
+        Some(Legend::new(&options.ramp))
+    } else {
+        None
+    };
+    let width = map_width + legend.as_ref().map_or(0, |l| l.width);
+    let height = map_height.max(legend.as_ref().map_or(0, |l| l.height));
+
+    let mut image = Image::new(width, height);
+    // rows are stored south to north, images are north up
+    for (row, values) in data.values.iter().rev().enumerate() {
+        for (column, value) in values.iter().enumerate() {
+            if let Some(rgb) = options.ramp.color(*value) {
+                image.fill(
+                    column * options.cell_pixels,
+                    row * options.cell_pixels,
+                    options.cell_pixels,
+                    options.cell_pixels,
+                    [rgb[0], rgb[1], rgb[2], 255],
+                );
+            }
+        }
+    }
+    if let Some(legend) = legend {
+        legend.draw(&mut image, map_width, &options.ramp);
+    }
+
+    let mut ihdr = Vec::new();
+    ihdr.extend(&(width as u32).to_be_bytes());
+    ihdr.extend(&(height as u32).to_be_bytes());
+    ihdr.extend(&[8, 6, 0, 0, 0]); // 8 bit RGBA, deflate, adaptive filters, no interlace
+
+    writer.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+    write_chunk(writer, b"IHDR", &ihdr)?;
+    write_chunk(writer, b"IDAT", &zlib_stored(&image.scanlines()))?;
+    write_chunk(writer, b"IEND", &[])
+}
+
+struct Image {
+    width: usize,
+    pixels: Vec<[u8; 4]>,
+}
+
+impl Image {
+    fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            pixels: vec![[0, 0, 0, 0]; width * height],
+        }
+    }
+
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, rgba: [u8; 4]) {
+        for row in y..y + height {
+            for column in x..x + width {
+                self.pixels[row * self.width + column] = rgba;
+            }
+        }
+    }
+
+    // each row with a leading filter type of 0, none
+    fn scanlines(&self) -> Vec<u8> {
+        self.pixels
+            .chunks(self.width)
+            .flat_map(|row| std::iter::once(0).chain(row.iter().flatten().copied()))
+            .collect()
+    }
+}
+
+// a white panel with a swatch and the break in mm for each colour, drawn with a 3 x 5 pixel font
+struct Legend {
+    width: usize,
+    height: usize,
+}
+
+const FONT_SCALE: usize = 2;
+const SWATCH: usize = 10;
+const LINE: usize = 14;
+const MARGIN: usize = 4;
+
+impl Legend {
+    fn new(ramp: &ColorRamp) -> Self {
+        let chars = ramp
+            .breaks
+            .iter()
+            .map(|(value, _)| label(*value).len())
+            .max()
+            .unwrap_or(0);
+        Legend {
+            width: MARGIN * 3 + SWATCH + chars * 4 * FONT_SCALE,
+            height: MARGIN * 2 + ramp.breaks.len() * LINE,
+        }
+    }
+
+    // highest break at the top
+    fn draw(&self, image: &mut Image, x: usize, ramp: &ColorRamp) {
+        let height = image.pixels.len() / image.width;
+        image.fill(x, 0, self.width, height, [255, 255, 255, 255]);
+
+        for (i, (value, rgb)) in ramp.breaks.iter().rev().enumerate() {
+            let y = MARGIN + i * LINE;
+            image.fill(x + MARGIN, y, SWATCH, SWATCH, [rgb[0], rgb[1], rgb[2], 255]);
+
+            let text_x = x + MARGIN * 2 + SWATCH;
+            for (n, c) in label(*value).chars().enumerate() {
+                draw_char(image, text_x + n * 4 * FONT_SCALE, y, c);
+            }
+        }
+    }
+}
+
+fn label(value: f64) -> String {
+    format!("{:.1}", value)
+}
+
+fn draw_char(image: &mut Image, x: usize, y: usize, c: char) {
+    let glyph: [u8; 5] = match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0; 5],
+    };
+
+    for (row, bits) in glyph.iter().enumerate() {
+        for column in 0..3 {
+            if bits & (0b100 >> column) != 0 {
+                image.fill(
+                    x + column * FONT_SCALE,
+                    y + row * FONT_SCALE,
+                    FONT_SCALE,
+                    FONT_SCALE,
+                    [0, 0, 0, 255],
+                );
+            }
+        }
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+    writer.write_all(&crc.finish().to_be_bytes())
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(if last { 1 } else { 0 });
+        out.extend(&len.to_le_bytes());
+        out.extend(&(!len).to_le_bytes());
+        out.extend(block);
+    }
+
+    out.extend(&adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+    use crate::utils::MISSING;
+
+    #[test]
+    fn write_png_test() {
+        let ramp = ColorRamp::nws_precipitation();
+        assert_eq!(ramp.color(0.0), None);
+        assert_eq!(ramp.color(MISSING), None);
+        assert_eq!(ramp.color(0.5), Some([0x04, 0xe9, 0xe7]));
+        assert_eq!(ramp.color(1000.0), Some([0xfd, 0xfd, 0xfd]));
+        let breaks: Vec<f64> = (1..=15).map(f64::from).collect();
+        assert_eq!(
+            ramp.with_breaks(&breaks).unwrap().color(1.5),
+            Some([0x04, 0xe9, 0xe7])
+        );
+        let error = ramp.with_breaks(&[1.0, 2.0]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let data = XmrgData::new(
+            Header::new(367, 263, 2, 2),
+            None,
+            vec![vec![0.0, 30.0], vec![MISSING, 300.0]],
+        );
+        let mut options = PngOptions::new();
+        options.legend = false;
+        options.cell_pixels = 2;
+
+        let mut bytes = Vec::new();
+        write_png_to(&mut bytes, &data, &options).unwrap();
+        assert_eq!(&bytes[1..4], b"PNG");
+        assert_eq!(&bytes[12..16], b"IHDR");
+        assert_eq!(
+            u32::from_be_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+            4
+        );
+
+        // zlib header and a single final stored block of 4 rows of 1 + 4 * 4 bytes
+        let idat = 8 + 25 + 8;
+        assert_eq!(&bytes[idat..idat + 3], &[0x78, 0x01, 1]);
+        // the north row comes first, missing then 300 mm
+        let first_row = idat + 7;
+        assert_eq!(&bytes[first_row..first_row + 5], &[0, 0, 0, 0, 0]);
+        assert_eq!(
+            &bytes[first_row + 9..first_row + 13],
+            &[0xfd, 0xfd, 0xfd, 255]
+        );
+
+        options.legend = true;
+        let mut with_legend = Vec::new();
+        write_png_to(&mut with_legend, &data, &options).unwrap();
+        assert!(with_legend.len() > bytes.len());
+    }
+}
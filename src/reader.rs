@@ -0,0 +1,308 @@
+use crate::endian::{get_endian, Endian};
+use crate::gzip::{is_gzip, GzDecoder};
+use crate::headers::{build_1997_reader, build_4_2_reader, build_5_2_2_reader, Header, Metadata};
+use crate::read_bytes::ReadBytes;
+use crate::utils::to_mm;
+use crate::xmrg_version::{get_xmrg_version, is_build_5_2_2, XmrgVersion};
+use crate::XmrgData;
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+// XMRG files are written as fortran unformatted sequential records, each record is wrapped in a
+// leading and trailing i32 holding the byte count of the record:
+// record 1: xor, yor, columns, rows
+// record 2: metadata (38 or 66 bytes) or, for pre 1997 files, the first row of data
+// record n: one row of i16 values, south to north
+
+pub struct Row {
+    pub y: i32,
+    pub values: Vec<f64>,
+}
+
+pub struct XmrgReader<R> {
+    reader: R,
+    endian: Endian,
+    header: Header,
+    metadata: Option<Metadata>,
+    row_started: bool, // pre 1997 files have already had the leading byte count of the first row read
+    current_row: i32,
+}
+
+// the input of XmrgReader::open. Gzipped files, as XMRG archives are usually distributed, are decompressed
+// as they are read
+pub enum XmrgFile {
+    Plain(BufReader<File>),
+    Gzip(Box<GzDecoder<BufReader<File>>>),
+}
+
+impl Read for XmrgFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            XmrgFile::Plain(reader) => reader.read(buf),
+            XmrgFile::Gzip(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl XmrgReader<XmrgFile> {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let input = if is_gzip(file.fill_buf()?) {
+            XmrgFile::Gzip(Box::new(GzDecoder::new(file)))
+        } else {
+            XmrgFile::Plain(file)
+        };
+
+        let mut reader = Self::new(input)?;
+        // a SHRAP file name gives the factor of windows too small to imply it
+        let factor = Header::factor_from_path(path);
+        if factor != 1 {
+            reader.header = reader.header.with_factor(factor)?;
+        }
+        Ok(reader)
+    }
+}
+
+impl<R: Read> XmrgReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let endian = get_endian(&mut reader)?;
+
+        // XMRG has no field for the subdivision factor, a window beyond the national HRAP grid implies one
+        let header = Header::from_vec(ReadBytes::new(4, endian).read_int32s(&mut reader)?);
+        let header = header.with_factor(header.factor_from_window())?;
+        endian.read::<i32>(&mut reader)?; // trailing byte count of record 1
+
+        let record_2_bytes: i32 = endian.read(&mut reader)?;
+
+        let (metadata, row_started) = match get_xmrg_version(record_2_bytes, header.columns()) {
+            Some(XmrgVersion::Pre1997) => (None, true),
+            Some(XmrgVersion::Build1997) => {
+                let h = build_1997_reader(&mut reader, endian)?;
+                (Some(Metadata::Header1997(h)), false)
+            }
+            Some(XmrgVersion::Build4_2) => {
+                let mut record = [0u8; 66];
+                reader.read_exact(&mut record)?;
+                let metadata = if is_build_5_2_2(&record) {
+                    Metadata::Header5_2_2(build_5_2_2_reader(&mut &record[..], endian)?)
+                } else {
+                    Metadata::Header4_2(build_4_2_reader(&mut &record[..], endian)?)
+                };
+                (Some(metadata), false)
+            }
+            None => {
+                let skip = u64::try_from(record_2_bytes).unwrap_or_default();
+                io::copy(&mut (&mut reader).take(skip), &mut io::sink())?;
+                (None, false)
+            }
+        };
+
+        if !row_started {
+            endian.read::<i32>(&mut reader)?; // trailing byte count of record 2
+        }
+
+        Ok(Self {
+            reader,
+            endian,
+            header,
+            metadata,
+            row_started,
+            current_row: 0,
+        })
+    }
+
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    // reads every remaining row into memory
+    pub fn into_data(mut self) -> io::Result<XmrgData> {
+        let values = self
+            .by_ref()
+            .map(|row| row.map(|r| r.values))
+            .collect::<io::Result<Vec<Vec<f64>>>>()?;
+
+        Ok(XmrgData::new(self.header, self.metadata, values))
+    }
+
+    fn read_row(&mut self) -> io::Result<Vec<f64>> {
+        if self.row_started {
+            self.row_started = false;
+        } else {
+            self.endian.read::<i32>(&mut self.reader)?;
+        }
+
+        let values = ReadBytes::new(self.header.columns(), self.endian)
+            .iter(&mut self.reader)
+            .map(|res| res.map(to_mm))
+            .collect();
+
+        self.endian.read::<i32>(&mut self.reader)?;
+
+        values
+    }
+}
+
+impl<R: Read> Iterator for XmrgReader<R> {
+    type Item = io::Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current_row >= self.header.rows() {
+            return None;
+        }
+
+        let y = self.header.yor() + self.current_row;
+        let row = self.read_row();
+
+        // stop after the first error, the position of the reader is unknown
+        self.current_row = if row.is_ok() {
+            self.current_row + 1
+        } else {
+            self.header.rows()
+        };
+
+        Some(row.map(|values| Row { y, values }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::Crc32;
+    use crate::headers::OperSys;
+
+    fn record(bytes: &[u8]) -> Vec<u8> {
+        let len = (bytes.len() as i32).to_le_bytes();
+        [&len[..], bytes, &len[..]].concat()
+    }
+
+    fn xmrg_bytes(metadata: Option<&[u8]>, rows: &[Vec<i16>]) -> Vec<u8> {
+        let header: Vec<u8> = [10, 20, rows[0].len() as i32, rows.len() as i32]
+            .iter()
+            .flat_map(|n| n.to_le_bytes().to_vec())
+            .collect();
+
+        let mut bytes = record(&header);
+        if let Some(m) = metadata {
+            bytes.extend(record(m));
+        }
+        for row in rows {
+            let row_bytes: Vec<u8> = row.iter().flat_map(|n| n.to_le_bytes().to_vec()).collect();
+            bytes.extend(record(&row_bytes));
+        }
+        bytes
+    }
+
+    #[test]
+    fn open_gzip_test() {
+        fn send<T: Send>(_: &T) {}
+
+        let plain = xmrg_bytes(None, &[vec![100, -1, 0], vec![250, 5, 1]]);
+        // a single stored deflate block holding the plain file
+        let mut gzip = vec![0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0x03, 0x01];
+        gzip.extend(&(plain.len() as u16).to_le_bytes());
+        gzip.extend(&(!(plain.len() as u16)).to_le_bytes());
+        gzip.extend(&plain);
+        let mut crc = Crc32::new();
+        crc.update(&plain);
+        gzip.extend(&crc.finish().to_le_bytes());
+        gzip.extend(&(plain.len() as u32).to_le_bytes());
+
+        let path = std::env::temp_dir().join(format!("rexmrg_open_{}.gz", std::process::id()));
+        std::fs::write(&path, &gzip).unwrap();
+        // the file is decompressed as rows are read, so stays open until then
+        let reader = XmrgReader::open(path.to_str().unwrap()).unwrap();
+        send(&reader);
+        let data = reader.into_data();
+        std::fs::remove_file(&path).unwrap();
+        let data = data.unwrap();
+        assert_eq!(data.values[1], vec![2.5, 0.05, 0.01]);
+    }
+
+    #[test]
+    fn reads_rows_with_y_test() {
+        let bytes = xmrg_bytes(None, &[vec![100, -1, 0], vec![250, 5, 1]]);
+
+        let reader = XmrgReader::new(&bytes[..]).unwrap();
+        assert!(reader.metadata().is_none());
+
+        let rows = reader.collect::<io::Result<Vec<Row>>>().unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].y, 20);
+        assert_eq!(rows[0].values, vec![1.0, -999.0, 0.0]);
+        assert_eq!(rows[1].y, 21);
+        assert_eq!(rows[1].values, vec![2.5, 0.05, 0.01]);
+    }
+
+    #[test]
+    fn reads_5_2_2_metadata_test() {
+        let mut metadata = b"LXuser1234".to_vec();
+        metadata.extend(b"2020-01-01 12:00:00\0");
+        metadata.extend(b"QPE01   ");
+        metadata.extend(b"2020-01-01 12:00:00\0");
+        metadata.extend(&250i32.to_le_bytes());
+        metadata.extend(&2.5f32.to_le_bytes());
+
+        let bytes = xmrg_bytes(Some(&metadata), &[vec![250, 0]]);
+
+        let data = XmrgReader::new(&bytes[..]).unwrap().into_data().unwrap();
+        let metadata = data.metadata.unwrap();
+
+        // a 66 byte record is the 5.2.2 layout with the operating system first
+        assert!(matches!(metadata, Metadata::Header5_2_2(_)));
+        assert!(matches!(metadata.os(), Some(OperSys::LX)));
+        assert_eq!(metadata.max_value(), Some(250));
+        assert_eq!(data.values, vec![vec![2.5, 0.0]]);
+    }
+
+    #[test]
+    fn reads_1997_metadata_test() {
+        let mut metadata = b"user123456".to_vec();
+        metadata.extend(b"2020-01-01 12:00:00\0");
+        metadata.extend(b"QPE01   ");
+
+        let bytes = xmrg_bytes(Some(&metadata), &[vec![250, 0]]);
+
+        let data = XmrgReader::new(&bytes[..]).unwrap().into_data().unwrap();
+        let metadata = data.metadata.unwrap();
+
+        // a 38 byte record stops at the process flag, with no valid time or max value
+        assert!(matches!(metadata, Metadata::Header1997(_)));
+        assert_eq!(metadata.user_id(), Some(String::from("user123456")));
+        assert_eq!(metadata.process_flag(), Some(String::from("QPE01   ")));
+        assert_eq!(metadata.max_value(), None);
+        assert_eq!(data.values, vec![vec![2.5, 0.0]]);
+    }
+
+    #[test]
+    fn reads_4_2_metadata_test() {
+        let mut metadata = b"user123456".to_vec();
+        metadata.extend(b"2001-06-01 12:00:00\0");
+        metadata.extend(b"QPE01   ");
+        metadata.extend(b"2001-06-01 12:00:00\0");
+        metadata.extend(&250i32.to_le_bytes());
+        metadata.extend(&4.2f32.to_le_bytes());
+
+        let bytes = xmrg_bytes(Some(&metadata), &[vec![250, 0]]);
+
+        let metadata = XmrgReader::new(&bytes[..])
+            .unwrap()
+            .into_data()
+            .unwrap()
+            .metadata
+            .unwrap();
+
+        assert!(matches!(metadata, Metadata::Header4_2(_)));
+        assert_eq!(metadata.user_id(), Some(String::from("user123456")));
+        assert_eq!(metadata.version(), Some(4.2));
+    }
+}
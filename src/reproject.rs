@@ -0,0 +1,364 @@
+use crate::geo::Point;
+use crate::hrap::{latlon_to_hrap_with, LonConvention};
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::io;
+
+// resampling onto a regular lat/lon grid. Each target cell is mapped back into HRAP space with latlon_to_hrap.
+// longitudes are conventional signed degrees (negative west)
+
+// points along each edge of a target cell outline, as lat/lon edges are curved in HRAP space
+const EDGE_POINTS: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Resampling {
+    Nearest,      // the HRAP cell holding the target cell centre
+    Bilinear,     // between the four HRAP cell centres around the target cell centre
+    Conservative, // the mean of the HRAP cells overlapping the target cell, weighted by overlap area
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LatLonExtent {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+impl LatLonExtent {
+    pub fn new(west: f64, south: f64, east: f64, north: f64) -> Self {
+        LatLonExtent {
+            west,
+            south,
+            east,
+            north,
+        }
+    }
+}
+
+// values are indexed rows x columns, south to north like XmrgData
+pub struct LatLonGrid {
+    pub west: f64,
+    pub south: f64,
+    pub resolution: f64,
+    pub columns: usize,
+    pub rows: usize,
+    pub values: Vec<Vec<f64>>,
+}
+
+impl LatLonGrid {
+    pub fn cell_center(&self, column: usize, row: usize) -> Point {
+        Point::new(
+            self.west + (column as f64 + 0.5) * self.resolution,
+            self.south + (row as f64 + 0.5) * self.resolution,
+        )
+    }
+}
+
+pub fn reproject(
+    data: &XmrgData,
+    extent: LatLonExtent,
+    resolution: f64,
+    method: Resampling,
+) -> io::Result<LatLonGrid> {
+    if !resolution.is_finite() || resolution <= 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "resolution must be a positive number of degrees, not {}",
+                resolution
+            ),
+        ));
+    }
+
+    let bounds = [extent.west, extent.south, extent.east, extent.north];
+    if bounds.iter().any(|b| !b.is_finite())
+        || extent.east <= extent.west
+        || extent.north <= extent.south
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "invalid extent {:?}, east must exceed west and north exceed south",
+                extent
+            ),
+        ));
+    }
+
+    let columns = ((extent.east - extent.west) / resolution).round().max(0.0) as usize;
+    let rows = ((extent.north - extent.south) / resolution)
+        .round()
+        .max(0.0) as usize;
+
+    let mut grid = LatLonGrid {
+        west: extent.west,
+        south: extent.south,
+        resolution,
+        columns,
+        rows,
+        values: Vec::new(),
+    };
+
+    grid.values = (0..rows)
+        .map(|row| {
+            (0..columns)
+                .map(|column| {
+                    let centre = grid.cell_center(column, row);
+                    match method {
+                        Resampling::Nearest => nearest_at(data, hrap_of(centre)),
+                        Resampling::Bilinear => bilinear_at(data, hrap_of(centre)),
+                        Resampling::Conservative => conservative_at(data, centre, resolution),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(grid)
+}
+
+fn hrap_of(lonlat: Point) -> Point {
+    latlon_to_hrap_with(lonlat.x, lonlat.y, LonConvention::Signed)
+}
+
+fn value(data: &XmrgData, column: i64, row: i64) -> Option<f64> {
+    if column < 0 || row < 0 {
+        return None;
+    }
+    data.values
+        .get(row as usize)
+        .and_then(|r| r.get(column as usize))
+        .copied()
+        .filter(|v| *v >= 0.0)
+}
+
+// the value of the cell holding an HRAP coordinate, MISSING outside of the grid
+pub(crate) fn nearest_at(data: &XmrgData, hrap: Point) -> f64 {
+    let position = data.header.grid_position(hrap);
+    value(data, position.x.round() as i64, position.y.round() as i64).unwrap_or(MISSING)
+}
+
+// interpolates between the four cell centres around an HRAP coordinate. Missing or out of grid neighbours are
+// dropped and the remaining weights renormalised, so edges and holes fall back toward the nearest cells
+pub(crate) fn bilinear_at(data: &XmrgData, hrap: Point) -> f64 {
+    let position = data.header.grid_position(hrap);
+    let column = position.x.floor();
+    let row = position.y.floor();
+    let fx = position.x - column;
+    let fy = position.y - row;
+
+    let corners = [
+        (0, 0, (1.0 - fx) * (1.0 - fy)),
+        (1, 0, fx * (1.0 - fy)),
+        (0, 1, (1.0 - fx) * fy),
+        (1, 1, fx * fy),
+    ];
+
+    let (sum, weight) = corners
+        .iter()
+        .filter(|(_, _, w)| *w > 0.0)
+        .filter_map(|(dx, dy, w)| {
+            value(data, column as i64 + dx, row as i64 + dy).map(|v| (v * w, *w))
+        })
+        .fold((0.0, 0.0), |(sum, weight), (v, w)| (sum + v, weight + w));
+
+    if weight > 0.0 {
+        sum / weight
+    } else {
+        MISSING
+    }
+}
+
+// the target cell outline is clipped against each HRAP cell it touches. Missing and out of grid cells are
+// dropped and the remaining weights renormalised, like bilinear_at
+fn conservative_at(data: &XmrgData, centre: Point, resolution: f64) -> f64 {
+    let outline = target_outline(data, centre, resolution);
+
+    let min = |f: fn(&Point) -> f64| outline.iter().map(f).fold(f64::INFINITY, f64::min);
+    let max = |f: fn(&Point) -> f64| outline.iter().map(f).fold(f64::NEG_INFINITY, f64::max);
+    let columns = min(|p| p.x).round() as i64..=max(|p| p.x).round() as i64;
+    let rows = min(|p| p.y).round() as i64..=max(|p| p.y).round() as i64;
+
+    let (sum, weight) = rows
+        .flat_map(|row| columns.clone().map(move |column| (column, row)))
+        .filter_map(|(column, row)| {
+            let v = value(data, column, row)?;
+            let overlap = area(&clip_to_cell(&outline, column as f64, row as f64));
+            Some((v * overlap, overlap))
+        })
+        .fold((0.0, 0.0), |(sum, weight), (v, w)| (sum + v, weight + w));
+
+    if weight > 0.0 {
+        sum / weight
+    } else {
+        MISSING
+    }
+}
+
+// the outline of a target cell in grid positions, where cell (c, r) spans c - 0.5 to c + 0.5
+fn target_outline(data: &XmrgData, centre: Point, resolution: f64) -> Vec<Point> {
+    let half = resolution / 2.0;
+    let corners = [
+        Point::new(centre.x - half, centre.y - half),
+        Point::new(centre.x + half, centre.y - half),
+        Point::new(centre.x + half, centre.y + half),
+        Point::new(centre.x - half, centre.y + half),
+    ];
+
+    (0..4)
+        .flat_map(|i| {
+            let (from, to) = (corners[i], corners[(i + 1) % 4]);
+            (0..EDGE_POINTS).map(move |k| {
+                let t = k as f64 / EDGE_POINTS as f64;
+                Point::new(from.x + (to.x - from.x) * t, from.y + (to.y - from.y) * t)
+            })
+        })
+        .map(|lonlat| data.header.grid_position(hrap_of(lonlat)))
+        .collect()
+}
+
+// Sutherland-Hodgman clipping of a polygon to the square of a cell, one side at a time as
+// (clip on x rather than y, limit, keep the side above the limit)
+fn clip_to_cell(polygon: &[Point], column: f64, row: f64) -> Vec<Point> {
+    let sides = [
+        (true, column - 0.5, true),
+        (true, column + 0.5, false),
+        (false, row - 0.5, true),
+        (false, row + 0.5, false),
+    ];
+
+    sides
+        .iter()
+        .fold(polygon.to_vec(), |points, (on_x, limit, above)| {
+            let coordinate = |p: &Point| if *on_x { p.x } else { p.y };
+            let inside = |p: &Point| {
+                if *above {
+                    coordinate(p) >= *limit
+                } else {
+                    coordinate(p) <= *limit
+                }
+            };
+
+            let mut clipped = Vec::new();
+            for (i, current) in points.iter().enumerate() {
+                let previous = &points[(i + points.len() - 1) % points.len()];
+                if inside(current) != inside(previous) {
+                    let t = (limit - coordinate(previous))
+                        / (coordinate(current) - coordinate(previous));
+                    clipped.push(Point::new(
+                        previous.x + (current.x - previous.x) * t,
+                        previous.y + (current.y - previous.y) * t,
+                    ));
+                }
+                if inside(current) {
+                    clipped.push(*current);
+                }
+            }
+            clipped
+        })
+}
+
+// shoelace area, in grid cells
+fn area(polygon: &[Point]) -> f64 {
+    let twice: f64 = (0..polygon.len())
+        .map(|i| {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum();
+    twice.abs() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+    use crate::hrap::{hrap_to_latlon, signed_longitude};
+
+    fn lonlat(header: Header, column: f64, row: f64) -> Point {
+        let hrap = header.hrap_point(0, 0);
+        let p = hrap_to_latlon(hrap.x + column, hrap.y + row);
+        Point::new(signed_longitude(p.x), p.y)
+    }
+
+    #[test]
+    fn bilinear_at_test() {
+        let header = Header::new(367, 263, 2, 2);
+        let data = XmrgData::new(header, None, vec![vec![0.0, 2.0], vec![4.0, MISSING]]);
+
+        let between = header.hrap_point(0, 0);
+        let half = Point::new(between.x + 0.5, between.y);
+        assert!((bilinear_at(&data, half) - 1.0).abs() < 1e-9);
+
+        // the missing corner is dropped and the other three reweighted
+        let middle = Point::new(between.x + 0.5, between.y + 0.5);
+        assert!((bilinear_at(&data, middle) - 2.0).abs() < 1e-9);
+
+        assert_eq!(bilinear_at(&data, Point::new(0.0, 0.0)), MISSING);
+    }
+
+    #[test]
+    fn reproject_test() {
+        let header = Header::new(367, 263, 20, 20);
+        let values = (0..20).map(|row| vec![row as f64; 20]).collect();
+        let data = XmrgData::new(header, None, values);
+
+        let sw = lonlat(header, 5.0, 5.0);
+        let ne = lonlat(header, 15.0, 15.0);
+        let extent = LatLonExtent::new(sw.x, sw.y, ne.x, ne.y);
+
+        for method in [
+            Resampling::Nearest,
+            Resampling::Bilinear,
+            Resampling::Conservative,
+        ]
+        .iter()
+        {
+            let grid = reproject(&data, extent, 0.05, *method).unwrap();
+            assert!(grid.rows > 0 && grid.columns > 0);
+
+            // values increase north and stay within the rows covered by the extent
+            let south = grid.values[0][grid.columns / 2];
+            let north = grid.values[grid.rows - 1][grid.columns / 2];
+            assert!(south >= 3.0 && north <= 17.0 && north > south);
+        }
+
+        for (resolution, extent) in [
+            (0.0, extent),
+            (f64::NAN, extent),
+            (0.05, LatLonExtent::new(ne.x, sw.y, sw.x, ne.y)),
+            (0.05, LatLonExtent::new(sw.x, sw.y, f64::INFINITY, ne.y)),
+        ]
+        .iter()
+        {
+            let error = reproject(&data, *extent, *resolution, Resampling::Nearest).err();
+            assert_eq!(error.map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+        }
+    }
+
+    #[test]
+    fn conservative_total_test() {
+        // one wet cell in a dry grid, under target cells several HRAP cells wide
+        let header = Header::new(367, 263, 40, 40);
+        let mut values = vec![vec![0.0; 40]; 40];
+        values[17][23] = 100.0;
+        let data = XmrgData::new(header, None, values);
+
+        let sw = lonlat(header, 5.0, 5.0);
+        let ne = lonlat(header, 35.0, 35.0);
+        let extent = LatLonExtent::new(sw.x, sw.y, ne.x, ne.y);
+        let resolution = 0.25;
+        let grid = reproject(&data, extent, resolution, Resampling::Conservative).unwrap();
+
+        // value times area, in HRAP cells, summed over the target grid is the value of the one wet cell
+        let total: f64 = (0..grid.rows)
+            .flat_map(|row| (0..grid.columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let outline = target_outline(&data, grid.cell_center(column, row), resolution);
+                grid.values[row][column] * area(&outline)
+            })
+            .sum();
+        assert!((total - 100.0).abs() < 1e-6);
+    }
+}
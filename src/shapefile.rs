@@ -0,0 +1,333 @@
+use crate::datetime::DateSegments;
+use crate::geo::Point;
+use crate::headers::CellPosition;
+use crate::hrap::{Coordinates, HrapProjection, LonConvention};
+use crate::XmrgData;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+// ESRI shapefiles, one shape per cell with a dBASE table of VALUE (mm), HRAP_X and HRAP_Y, the cell's
+// coordinates in the units of the grid like xor and yor. Missing values are written as -999.00
+// https://www.esri.com/content/dam/esrisites/sitecore-archive/Files/Pdfs/library/whitepapers/pdfs/shapefile.pdf
+
+const FILE_CODE: i32 = 9994;
+const VERSION: i32 = 1000;
+const HEADER_BYTES: usize = 100;
+
+const POINT: i32 = 1;
+const POLYGON: i32 = 5;
+
+// dBASE fields: name, length, decimal places
+const FIELDS: [(&str, usize, u8); 3] = [("VALUE", 12, 2), ("HRAP_X", 8, 0), ("HRAP_Y", 8, 0)];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShapeType {
+    Points,   // cell centres
+    Polygons, // cell outlines
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShapefileOptions {
+    pub shape_type: ShapeType,
+    pub coordinates: Coordinates,
+    pub skip_missing: bool,
+    pub min_value: Option<f64>, // cells below are left out, as are missing cells
+}
+
+impl ShapefileOptions {
+    // every cell, in signed lat/lon
+    pub fn new(shape_type: ShapeType) -> Self {
+        ShapefileOptions {
+            shape_type,
+            coordinates: Coordinates::LatLon(LonConvention::Signed),
+            skip_missing: false,
+            min_value: None,
+        }
+    }
+
+    fn keeps(&self, value: f64) -> bool {
+        match self.min_value {
+            Some(min) => value >= 0.0 && value >= min,
+            None => !(self.skip_missing && value < 0.0),
+        }
+    }
+}
+
+// writes path.shp, .shx, .dbf and .prj, path may be given with or without .shp
+pub fn write_shapefile(path: &str, data: &XmrgData, options: &ShapefileOptions) -> io::Result<()> {
+    let base = path.strip_suffix(".shp").unwrap_or(path);
+
+    let mut shp = BufWriter::new(File::create(format!("{}.shp", base))?);
+    let mut shx = BufWriter::new(File::create(format!("{}.shx", base))?);
+    let mut dbf = BufWriter::new(File::create(format!("{}.dbf", base))?);
+    write_shapefile_to(&mut shp, &mut shx, &mut dbf, data, options)?;
+    shp.flush()?;
+    shx.flush()?;
+    dbf.flush()?;
+
+    // positive west longitudes have no standard definition, so get no .prj
+    let prj = match options.coordinates {
+        Coordinates::LatLon(LonConvention::Signed) => HrapProjection::default().geographic_wkt(),
+        Coordinates::Meters => HrapProjection::default().wkt(),
+        Coordinates::LatLon(LonConvention::PositiveWest) => return Ok(()),
+    };
+    std::fs::write(format!("{}.prj", base), prj)
+}
+
+pub fn write_shapefile_to<S: Write, X: Write, D: Write>(
+    shp: &mut S,
+    shx: &mut X,
+    dbf: &mut D,
+    data: &XmrgData,
+    options: &ShapefileOptions,
+) -> io::Result<()> {
+    let header = data.header;
+
+    // (grid x, grid y, value, shape)
+    let mut shapes: Vec<(i32, i32, f64, Vec<Point>)> = Vec::new();
+    for (row, values) in data.values.iter().enumerate() {
+        for (column, value) in values.iter().enumerate() {
+            if !options.keeps(*value) {
+                continue;
+            }
+
+            let corner = header.cell_point(column, row, CellPosition::LowerLeft);
+            let size = header.cell_size();
+            let points = match options.shape_type {
+                ShapeType::Points => {
+                    let centre = header.hrap_point(column, row);
+                    vec![options.coordinates.locate(centre.x, centre.y)]
+                }
+                ShapeType::Polygons => clockwise(
+                    [
+                        (0.0, 0.0),
+                        (0.0, size),
+                        (size, size),
+                        (size, 0.0),
+                        (0.0, 0.0),
+                    ]
+                    .iter()
+                    .map(|(dx, dy)| options.coordinates.locate(corner.x + dx, corner.y + dy))
+                    .collect(),
+                ),
+            };
+
+            shapes.push((
+                header.xor() + column as i32,
+                header.yor() + row as i32,
+                *value,
+                points,
+            ));
+        }
+    }
+
+    let shape_type = match options.shape_type {
+        ShapeType::Points => POINT,
+        ShapeType::Polygons => POLYGON,
+    };
+
+    let contents: Vec<Vec<u8>> = shapes
+        .iter()
+        .map(|(_, _, _, points)| record_content(shape_type, points))
+        .collect();
+    let bounds = bounds(shapes.iter().flat_map(|(_, _, _, points)| points.iter()));
+
+    let shp_len = HEADER_BYTES + contents.iter().map(|c| 8 + c.len()).sum::<usize>();
+    let shx_len = HEADER_BYTES + 8 * contents.len();
+
+    shp.write_all(&file_header(shape_type, shp_len, bounds))?;
+    shx.write_all(&file_header(shape_type, shx_len, bounds))?;
+
+    let mut offset = HEADER_BYTES;
+    for (i, content) in contents.iter().enumerate() {
+        shp.write_all(&(i as i32 + 1).to_be_bytes())?;
+        shp.write_all(&words(content.len()).to_be_bytes())?;
+        shp.write_all(content)?;
+
+        shx.write_all(&words(offset).to_be_bytes())?;
+        shx.write_all(&words(content.len()).to_be_bytes())?;
+        offset += 8 + content.len();
+    }
+
+    let rows: Vec<[f64; 3]> = shapes
+        .iter()
+        .map(|(x, y, value, _)| [*value, f64::from(*x), f64::from(*y)])
+        .collect();
+    // the grid's valid time rather than today, so the same grid always gives the same bytes
+    let date = data
+        .valid_time()
+        .unwrap_or_else(|| DateSegments::from_hours(0));
+    write_dbf(dbf, &rows, date)
+}
+
+fn words(bytes: usize) -> i32 {
+    (bytes / 2) as i32
+}
+
+fn file_header(shape_type: i32, bytes: usize, bounds: [f64; 4]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_BYTES);
+    header.extend(&FILE_CODE.to_be_bytes());
+    header.extend(&[0; 20]);
+    header.extend(&words(bytes).to_be_bytes());
+    header.extend(&VERSION.to_le_bytes());
+    header.extend(&shape_type.to_le_bytes());
+    for b in bounds.iter() {
+        header.extend(&b.to_le_bytes());
+    }
+    header.extend(&[0; 32]); // z and m ranges
+    header
+}
+
+fn record_content(shape_type: i32, points: &[Point]) -> Vec<u8> {
+    let mut content = Vec::new();
+    content.extend(&shape_type.to_le_bytes());
+
+    if shape_type == POLYGON {
+        for b in bounds(points.iter()).iter() {
+            content.extend(&b.to_le_bytes());
+        }
+        content.extend(&1i32.to_le_bytes()); // parts
+        content.extend(&(points.len() as i32).to_le_bytes());
+        content.extend(&0i32.to_le_bytes()); // the part starts at the first point
+    }
+
+    for p in points {
+        content.extend(&p.x.to_le_bytes());
+        content.extend(&p.y.to_le_bytes());
+    }
+    content
+}
+
+// xmin, ymin, xmax, ymax, all zero when there are no points
+fn bounds<'a>(points: impl Iterator<Item = &'a Point>) -> [f64; 4] {
+    let b = points.fold(
+        [f64::MAX, f64::MAX, f64::MIN, f64::MIN],
+        |[xmin, ymin, xmax, ymax], p| [xmin.min(p.x), ymin.min(p.y), xmax.max(p.x), ymax.max(p.y)],
+    );
+    if b[0] > b[2] {
+        [0.0; 4]
+    } else {
+        b
+    }
+}
+
+// outer rings are clockwise, which positive west longitudes reverse
+fn clockwise(mut ring: Vec<Point>) -> Vec<Point> {
+    let twice_area: f64 = ring
+        .windows(2)
+        .map(|w| w[0].x * w[1].y - w[1].x * w[0].y)
+        .sum();
+    if twice_area > 0.0 {
+        ring.reverse();
+    }
+    ring
+}
+
+fn write_dbf<W: Write>(writer: &mut W, rows: &[[f64; 3]], date: DateSegments) -> io::Result<()> {
+    let header_len = 32 + 32 * FIELDS.len() + 1;
+    let record_len = 1 + FIELDS.iter().map(|(_, len, _)| len).sum::<usize>();
+
+    let mut header = vec![
+        0x03,
+        (date.year - 1900) as u8,
+        date.month as u8,
+        date.day as u8,
+    ];
+    header.extend(&(rows.len() as u32).to_le_bytes());
+    header.extend(&(header_len as u16).to_le_bytes());
+    header.extend(&(record_len as u16).to_le_bytes());
+    header.extend(&[0; 20]);
+
+    for (name, len, decimals) in FIELDS.iter() {
+        let mut field = [0u8; 32];
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        field[11] = b'N';
+        field[16] = *len as u8;
+        field[17] = *decimals;
+        header.extend(&field);
+    }
+    header.push(0x0d);
+    writer.write_all(&header)?;
+
+    for row in rows {
+        let mut record = String::from(" ");
+        for ((_, len, decimals), value) in FIELDS.iter().zip(row.iter()) {
+            record.push_str(&format!(
+                "{:>width$.precision$}",
+                value,
+                width = *len,
+                precision = *decimals as usize
+            ));
+        }
+        writer.write_all(record.as_bytes())?;
+    }
+
+    writer.write_all(&[0x1a])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::{Header, Metadata};
+    use crate::utils::MISSING;
+    use std::convert::TryInto;
+
+    fn read_i32_be(bytes: &[u8], at: usize) -> i32 {
+        i32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    fn read_i32_le(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn write_shapefile_test() {
+        let header = Header::new(367, 263, 2, 2);
+        let data = XmrgData::new(header, None, vec![vec![1.5, MISSING], vec![0.25, 3.0]]);
+
+        let mut options = ShapefileOptions::new(ShapeType::Polygons);
+        options.skip_missing = true;
+
+        let (mut shp, mut shx, mut dbf) = (Vec::new(), Vec::new(), Vec::new());
+        write_shapefile_to(&mut shp, &mut shx, &mut dbf, &data, &options).unwrap();
+
+        assert_eq!(read_i32_be(&shp, 0), FILE_CODE);
+        assert_eq!(read_i32_be(&shp, 24) as usize * 2, shp.len());
+        assert_eq!(read_i32_le(&shp, 32), POLYGON);
+        assert_eq!(shx.len(), 100 + 3 * 8);
+
+        // each polygon is 5 points, the second record starts after the first
+        assert_eq!(read_i32_be(&shp, 104), 64);
+        assert_eq!(read_i32_be(&shx, 108), (100 + 8 + 128) / 2);
+
+        // west longitudes, so the clockwise ring runs north up the west side first
+        let x0 = f64::from_le_bytes(shp[156..164].try_into().unwrap());
+        let y1 = f64::from_le_bytes(shp[180..188].try_into().unwrap());
+        let y0 = f64::from_le_bytes(shp[164..172].try_into().unwrap());
+        assert!(x0 < 0.0 && y1 > y0);
+
+        // no valid time, so the epoch
+        assert_eq!(dbf[1..4], [70, 1, 1]);
+        assert_eq!(u32::from_le_bytes(dbf[4..8].try_into().unwrap()), 3);
+        let records = &dbf[32 + 96 + 1..];
+        assert_eq!(&records[..29], b"         1.50     367     263".as_ref());
+        assert_eq!(records.len(), 3 * 29 + 1);
+
+        options.min_value = Some(1.0);
+        let (mut shp, mut shx, mut dbf) = (Vec::new(), Vec::new(), Vec::new());
+        options.shape_type = ShapeType::Points;
+        write_shapefile_to(&mut shp, &mut shx, &mut dbf, &data, &options).unwrap();
+        assert_eq!(shp.len(), 100 + 2 * (8 + 20));
+
+        // the dbf is dated by the grid, not by the clock
+        let values = data.values.clone();
+        let metadata = Metadata::for_product(DateSegments::new(2021, 6, 3, 12), "QPE01H", &values);
+        let data = XmrgData::new(header, Some(metadata), values);
+        let mut dbf = Vec::new();
+        write_shapefile_to(&mut Vec::new(), &mut Vec::new(), &mut dbf, &data, &options).unwrap();
+        assert_eq!(dbf[1..4], [121, 6, 3]);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::datetime::{read_xmrg_date, DateSegments};
+use crate::headers::Header;
+use crate::read_xmrg;
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::fs;
+use std::io;
+
+// an hourly time series of grids sharing one HRAP window
+// values are indexed time x rows x columns, hours with no file are filled with MISSING
+pub struct GridStack {
+    pub header: Header,
+    pub times: Vec<DateSegments>,
+    pub values: Vec<Vec<Vec<f64>>>,
+    pub missing: Vec<DateSegments>,
+}
+
+impl GridStack {
+    pub fn from_grids(mut grids: Vec<(DateSegments, XmrgData)>) -> io::Result<Self> {
+        grids.sort_by_key(|(time, _)| *time);
+
+        let header = match grids.first() {
+            Some((_, data)) => data.header,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no xmrg grids to stack",
+                ))
+            }
+        };
+
+        if let Some((time, _)) = grids.iter().find(|(_, data)| data.header != header) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("grid at {} does not share the header {:?}", time, header),
+            ));
+        }
+
+        if let Some(pair) = grids.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("more than one grid is valid at {}", pair[0].0),
+            ));
+        }
+
+        let first = grids[0].0.hours_since_epoch();
+        let last = grids[grids.len() - 1].0.hours_since_epoch();
+
+        let mut times = Vec::new();
+        let mut values = Vec::new();
+        let mut missing = Vec::new();
+        let mut grids = grids.into_iter().peekable();
+
+        for hour in first..=last {
+            let time = DateSegments::from_hours(hour);
+            times.push(time);
+
+            match grids.next_if(|(t, _)| *t == time) {
+                Some((_, data)) => values.push(data.values),
+                None => {
+                    missing.push(time);
+                    values.push(missing_grid(header));
+                }
+            }
+        }
+
+        Ok(GridStack {
+            header,
+            times,
+            values,
+            missing,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub fn is_missing(&self, index: usize) -> bool {
+        self.missing.binary_search(&self.times[index]).is_ok()
+    }
+
+    pub fn time_index(&self, time: DateSegments) -> Option<usize> {
+        self.times.binary_search(&time).ok()
+    }
+}
+
+// the valid time in the header if there is one, otherwise the date in the file name
+pub fn valid_time(path: &str, data: &XmrgData) -> Option<DateSegments> {
+    data.valid_time().or_else(|| read_xmrg_date(path))
+}
+
+pub fn read_stack(paths: &[String]) -> io::Result<GridStack> {
+    let grids = paths
+        .iter()
+        .map(|path| {
+            let data = read_xmrg(path)?;
+            match valid_time(path, &data) {
+                Some(time) => Ok((time, data)),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("could not find a valid time for {}", path),
+                )),
+            }
+        })
+        .collect::<io::Result<Vec<(DateSegments, XmrgData)>>>()?;
+
+    GridStack::from_grids(grids)
+}
+
+// reads every file in dir whose name matches pattern, where * matches any run of characters and ? any one character
+pub fn read_stack_dir(dir: &str, pattern: &str) -> io::Result<GridStack> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let matched = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| matches_pattern(pattern, name));
+
+        if matched && path.is_file() {
+            paths.push(path.to_string_lossy().into_owned());
+        }
+    }
+
+    read_stack(&paths)
+}
+
+pub(crate) fn missing_grid(header: Header) -> Vec<Vec<f64>> {
+    vec![vec![MISSING; header.columns() as usize]; header.rows() as usize]
+}
+
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    // classic wildcard matching with backtracking to the last *
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(header: Header, value: f64) -> XmrgData {
+        let values = vec![vec![value; header.columns() as usize]; header.rows() as usize];
+        XmrgData::new(header, None, values)
+    }
+
+    #[test]
+    fn matches_pattern_test() {
+        assert!(matches_pattern("xmrg*z.gz", "xmrg0506199516z.gz"));
+        assert!(matches_pattern("*", "anything"));
+        assert!(matches_pattern("xmrg05??199516z*", "xmrg0506199516z.gz"));
+        assert!(!matches_pattern("xmrg*z.bin", "xmrg0506199516z.gz"));
+    }
+
+    #[test]
+    fn from_grids_fills_missing_hours_test() {
+        let header = Header::new(10, 20, 2, 1);
+        let t0 = DateSegments::new(2020, 12, 31, 22);
+
+        let stack = GridStack::from_grids(vec![
+            (t0.add_hours(3), grid(header, 3.0)),
+            (t0, grid(header, 1.0)),
+        ])
+        .unwrap();
+
+        assert_eq!(stack.len(), 4);
+        assert_eq!(stack.times[2], DateSegments::new(2021, 1, 1, 0));
+        assert_eq!(stack.missing, vec![t0.add_hours(1), t0.add_hours(2)]);
+        assert!(stack.is_missing(1));
+        assert_eq!(stack.values[0][0][0], 1.0);
+        assert_eq!(stack.values[1][0][0], MISSING);
+        assert_eq!(stack.values[3][0][1], 3.0);
+    }
+
+    #[test]
+    fn from_grids_rejects_mismatched_headers_test() {
+        let t0 = DateSegments::new(2020, 1, 1, 0);
+
+        let stack = GridStack::from_grids(vec![
+            (t0, grid(Header::new(10, 20, 2, 1), 1.0)),
+            (t0.add_hours(1), grid(Header::new(11, 20, 2, 1), 1.0)),
+        ]);
+
+        assert!(stack.is_err());
+    }
+}
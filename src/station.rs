@@ -0,0 +1,96 @@
+use crate::geo::Point;
+use crate::XmrgData;
+
+// a point location such as a rain gauge, longitude signed (negative west)
+#[derive(Debug, Clone)]
+pub struct Station {
+    pub id: String,
+    pub location: Point,
+}
+
+// one row of a station sampling table, values are MISSING where the grid has no data
+#[derive(Debug, Clone)]
+pub struct StationSample {
+    pub id: String,
+    pub location: Point,
+    pub nearest: f64,
+    pub bilinear: f64,
+}
+
+impl Station {
+    pub fn new(id: &str, lon: f64, lat: f64) -> Self {
+        Station {
+            id: String::from(id),
+            location: Point::new(lon, lat),
+        }
+    }
+}
+
+impl StationSample {
+    pub fn csv_row(&self) -> String {
+        // id long lat nearest bilinear
+        format!(
+            "{},{},{},{},{}",
+            self.id, self.location.x, self.location.y, self.nearest, self.bilinear
+        )
+    }
+}
+
+pub fn sample_stations(data: &XmrgData, stations: &[Station]) -> Vec<StationSample> {
+    stations
+        .iter()
+        .map(|station| StationSample {
+            id: station.id.clone(),
+            location: station.location,
+            nearest: data.sample(station.location.x, station.location.y),
+            bilinear: data.sample_bilinear(station.location.x, station.location.y),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+    use crate::hrap::{hrap_to_latlon, signed_longitude};
+    use crate::utils::MISSING;
+
+    #[test]
+    fn sample_stations_test() {
+        let header = Header::new(367, 263, 3, 3);
+        let data = XmrgData::new(
+            header,
+            None,
+            vec![
+                vec![0.0, 1.0, 2.0],
+                vec![3.0, 4.0, MISSING],
+                vec![6.0, 7.0, 8.0],
+            ],
+        );
+
+        let at = |column: f64, row: f64| {
+            let hrap = header.hrap_point(0, 0);
+            let p = hrap_to_latlon(hrap.x + column, hrap.y + row);
+            (signed_longitude(p.x), p.y)
+        };
+
+        let (lon, lat) = at(0.5, 0.0);
+        let (far_lon, far_lat) = at(10.0, 10.0);
+        let (edge_lon, edge_lat) = at(2.25, 0.75);
+        let stations = vec![
+            Station::new("A", lon, lat),
+            Station::new("B", far_lon, far_lat),
+            Station::new("C", edge_lon, edge_lat),
+        ];
+
+        let table = sample_stations(&data, &stations);
+        assert!((table[0].bilinear - 0.5).abs() < 1e-6);
+        assert_eq!(table[1].nearest, MISSING);
+        assert_eq!(table[1].bilinear, MISSING);
+
+        // beyond the last column centre, and next to a missing cell, only the remaining neighbours count
+        assert_eq!(table[2].nearest, MISSING);
+        assert!((table[2].bilinear - 2.0).abs() < 1e-6);
+        assert!(table[0].csv_row().starts_with("A,-"));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::png::ColorRamp;
+use crate::XmrgData;
+
+// quick look rendering for terminals, north up. Each character is an upper half block with the foreground
+// colour for the top pixel and the background colour for the bottom one, and each pixel is a square block of
+// cells, so a grid fits the given width. Colours are the nearest of the 256 colour xterm palette cube
+
+const UPPER_HALF: char = '\u{2580}';
+const FULL: char = '\u{2588}';
+const RESET: &str = "\x1b[0m";
+
+const MISSING_COLOR: u8 = 238; // dark grey
+const DRY_COLOR: u8 = 16; // black, below the first break of the ramp
+
+// the intensities of the six steps of the xterm colour cube
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+pub fn render_heatmap(data: &XmrgData, width: usize, ramp: &ColorRamp) -> String {
+    let pixels = downsample(data, width);
+    let color = |value: Option<f64>| match value {
+        None => MISSING_COLOR,
+        Some(value) => ramp.color(value).map_or(DRY_COLOR, ansi_256),
+    };
+
+    let mut text = String::new();
+    for pair in pixels.chunks(2) {
+        for (i, top) in pair[0].iter().enumerate() {
+            text.push_str(&format!("\x1b[38;5;{}m", color(*top)));
+            match pair.get(1) {
+                Some(bottom) => text.push_str(&format!("\x1b[48;5;{}m", color(bottom[i]))),
+                None => text.push_str("\x1b[49m"),
+            }
+            text.push(UPPER_HALF);
+        }
+        text.push_str(RESET);
+        text.push('\n');
+    }
+    text
+}
+
+// a swatch for each break labelled with its lower bound in mm, wrapped to the width
+pub fn render_legend(ramp: &ColorRamp, width: usize) -> String {
+    let mut items = vec![
+        (MISSING_COLOR, String::from("missing")),
+        (DRY_COLOR, String::from("dry")),
+    ];
+    items.extend(
+        ramp.breaks
+            .iter()
+            .map(|(at, rgb)| (ansi_256(*rgb), trim_number(*at))),
+    );
+
+    let mut text = String::from("mm ");
+    let mut line_width = 3;
+    for (color, label) in items {
+        let item_width = label.chars().count() + 3;
+        if line_width + item_width > width && line_width > 3 {
+            text.push('\n');
+            text.push_str("   ");
+            line_width = 3;
+        }
+        text.push_str(&format!(
+            "\x1b[38;5;{}m{}{}{} {} ",
+            color, FULL, FULL, RESET, label
+        ));
+        line_width += item_width + 1;
+    }
+    text.push('\n');
+    text
+}
+
+// the header and a count of the values, enough to tell an empty or all missing grid at a glance
+pub fn summary(data: &XmrgData) -> String {
+    let header = &data.header;
+    let values = data.values.iter().flatten();
+
+    let missing = values.clone().filter(|v| **v < 0.0).count();
+    let zero = values.clone().filter(|v| **v == 0.0).count();
+    let wet: Vec<f64> = values.copied().filter(|v| *v > 0.0).collect();
+    let max = wet.iter().copied().fold(0.0, f64::max);
+    let mean = if wet.is_empty() {
+        0.0
+    } else {
+        wet.iter().sum::<f64>() / wet.len() as f64
+    };
+
+    let valid = data
+        .valid_time()
+        .map_or_else(|| String::from("unknown"), |time| time.to_string());
+    let process = data
+        .metadata
+        .as_ref()
+        .and_then(|m| m.process_flag())
+        .map_or_else(|| String::from("none"), |flag| flag.trim().to_string());
+
+    format!(
+        "grid: {} x {} cells from HRAP ({}, {}), subdivision {}\n\
+         valid: {}, process flag: {}\n\
+         cells: {} wet, {} zero, {} missing\n\
+         max: {:.2} mm, mean of wet cells: {:.2} mm\n",
+        header.columns(),
+        header.rows(),
+        header.xor(),
+        header.yor(),
+        header.factor(),
+        valid,
+        process,
+        wet.len(),
+        zero,
+        missing,
+        max,
+        mean
+    )
+}
+
+// rows north to south of block means. A block with any missing cell is missing, so that one cell wide stripes
+// of missing data still show when many cells share a character
+fn downsample(data: &XmrgData, width: usize) -> Vec<Vec<Option<f64>>> {
+    let columns = data.header.columns().max(0) as usize;
+    let rows = data.values.len();
+    let step = columns.div_ceil(width.max(1)).max(1);
+
+    (0..rows.div_ceil(step))
+        .rev()
+        .map(|block_row| {
+            (0..columns.div_ceil(step))
+                .map(|block_column| {
+                    let block: Vec<f64> = data.values
+                        [block_row * step..rows.min((block_row + 1) * step)]
+                        .iter()
+                        .flat_map(|row| row.iter().skip(block_column * step).take(step))
+                        .copied()
+                        .collect();
+                    if block.is_empty() || block.iter().any(|v| *v < 0.0) {
+                        None
+                    } else {
+                        Some(block.iter().sum::<f64>() / block.len() as f64)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn ansi_256(rgb: [u8; 3]) -> u8 {
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|i| (i32::from(CUBE_LEVELS[*i]) - i32::from(c)).abs())
+            .unwrap_or(0) as u8
+    };
+    16 + 36 * level(rgb[0]) + 6 * level(rgb[1]) + level(rgb[2])
+}
+
+// up to two decimals without trailing zeros
+fn trim_number(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+    use crate::utils::MISSING;
+
+    #[test]
+    fn render_heatmap_test() {
+        assert_eq!(ansi_256([0, 0, 0]), 16);
+        assert_eq!(ansi_256([0xfd, 0x00, 0x00]), 196);
+        assert_eq!(ansi_256([0xfd, 0xfd, 0xfd]), 231);
+
+        // 4 x 3 cells at 2 cells per pixel, the north west pixel has one missing cell
+        let data = XmrgData::new(
+            Header::new(10, 10, 4, 3),
+            None,
+            vec![
+                vec![0.0, 0.0, 100.0, 100.0],
+                vec![0.0, 0.0, 100.0, 100.0],
+                vec![MISSING, 0.0, 0.0, 0.0],
+            ],
+        );
+        let ramp = ColorRamp::nws_precipitation();
+
+        let text = render_heatmap(&data, 2, &ramp);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].chars().filter(|c| *c == UPPER_HALF).count(), 2);
+        assert!(lines[0].starts_with("\x1b[38;5;238m\x1b[48;5;16m"));
+        // the north row is the top half of the line, 100 mm is above the 3 inch break
+        assert!(lines[0].ends_with("\x1b[38;5;16m\x1b[48;5;196m\u{2580}\x1b[0m"));
+
+        let summary = summary(&data);
+        assert!(summary.contains("cells: 4 wet, 7 zero, 1 missing"));
+        assert!(summary.contains("max: 100.00 mm"));
+    }
+}
@@ -1,9 +1,41 @@
+use std::io;
+
+pub const MISSING: f64 = -999.0;
+
 // if a data point is negative, represent as -999 (no data), if positive, divide by 100 to represent in millimeters
 // data points are represented as a 100th of a milimeter. .001mm is represented as 1 in a xmrg data point, dividing by 100 gets us to .001
 pub fn to_mm(data_point: i16) -> f64 {
     if data_point < 0 {
-        -999.0
+        MISSING
     } else {
         data_point as f64 / 100.0
     }
 }
+
+// the inverse of to_mm. MISSING is written as -999, other negative values, such as differences from
+// XmrgData::subtract, and values above 327.67mm cannot be stored in XMRG
+pub fn from_mm(value: f64) -> io::Result<i16> {
+    if value == MISSING {
+        return Ok(-999);
+    }
+    if value < 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} mm cannot be stored in XMRG, where negative values mean missing",
+                value
+            ),
+        ));
+    }
+    let hundredths = (value * 100.0).round();
+    if hundredths > f64::from(i16::MAX) || hundredths.is_nan() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} mm does not fit in XMRG hundredths of a millimeter (at most 327.67)",
+                value
+            ),
+        ));
+    }
+    Ok(hundredths as i16)
+}
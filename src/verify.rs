@@ -0,0 +1,216 @@
+use crate::station::Station;
+use crate::XmrgData;
+
+use std::fs;
+use std::io;
+
+// gauge vs radar verification for a single hour. Differences are radar minus gauge in millimeters
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sampling {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Debug, Clone)]
+pub struct GaugeObservation {
+    pub station: Station,
+    pub observed: f64,
+}
+
+// radar is MISSING where the grid has no data at the gauge, such pairs are left out of the statistics
+#[derive(Debug, Clone)]
+pub struct GaugePair {
+    pub station: Station,
+    pub observed: f64,
+    pub radar: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub pairs: Vec<GaugePair>,
+    pub count: usize,
+    pub bias: f64,       // mean difference
+    pub bias_ratio: f64, // radar total over gauge total
+    pub mae: f64,
+    pub rmse: f64,
+    pub correlation: f64,
+}
+
+impl GaugePair {
+    pub fn is_valid(&self) -> bool {
+        self.observed >= 0.0 && self.radar >= 0.0
+    }
+
+    pub fn difference(&self) -> f64 {
+        self.radar - self.observed
+    }
+
+    pub fn csv_row(&self) -> String {
+        // id long lat gauge radar difference
+        let difference = if self.is_valid() {
+            format!("{:.2}", self.difference())
+        } else {
+            String::new()
+        };
+        format!(
+            "{},{},{},{:.2},{:.2},{}",
+            self.station.id,
+            self.station.location.x,
+            self.station.location.y,
+            self.observed,
+            self.radar,
+            difference
+        )
+    }
+}
+
+impl Verification {
+    // statistics are NaN when no pairs are valid, the correlation also when either side is constant
+    pub fn new(pairs: Vec<GaugePair>) -> Self {
+        let valid: Vec<(f64, f64)> = pairs
+            .iter()
+            .filter(|p| p.is_valid())
+            .map(|p| (p.observed, p.radar))
+            .collect();
+        let n = valid.len() as f64;
+
+        let gauge_total: f64 = valid.iter().map(|(g, _)| g).sum();
+        let radar_total: f64 = valid.iter().map(|(_, r)| r).sum();
+        let gauge_mean = gauge_total / n;
+        let radar_mean = radar_total / n;
+
+        let bias = (radar_total - gauge_total) / n;
+        let mae = valid.iter().map(|(g, r)| (r - g).abs()).sum::<f64>() / n;
+        let rmse = (valid.iter().map(|(g, r)| (r - g).powi(2)).sum::<f64>() / n).sqrt();
+
+        let (covariance, gauge_variance, radar_variance) =
+            valid.iter().fold((0.0, 0.0, 0.0), |(c, gv, rv), (g, r)| {
+                let dg = g - gauge_mean;
+                let dr = r - radar_mean;
+                (c + dg * dr, gv + dg * dg, rv + dr * dr)
+            });
+        let correlation = covariance / (gauge_variance * radar_variance).sqrt();
+
+        Verification {
+            count: valid.len(),
+            bias,
+            bias_ratio: radar_total / gauge_total,
+            mae,
+            rmse,
+            correlation,
+            pairs,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "pairs {}\nbias {:.3} mm\nbias ratio {:.3}\nmae {:.3} mm\nrmse {:.3} mm\ncorrelation {:.3}",
+            self.count, self.bias, self.bias_ratio, self.mae, self.rmse, self.correlation
+        )
+    }
+}
+
+pub fn verify(data: &XmrgData, gauges: &[GaugeObservation], sampling: Sampling) -> Verification {
+    let pairs = gauges
+        .iter()
+        .map(|gauge| {
+            let location = gauge.station.location;
+            let radar = match sampling {
+                Sampling::Nearest => data.sample(location.x, location.y),
+                Sampling::Bilinear => data.sample_bilinear(location.x, location.y),
+            };
+            GaugePair {
+                station: gauge.station.clone(),
+                observed: gauge.observed,
+                radar,
+            }
+        })
+        .collect();
+
+    Verification::new(pairs)
+}
+
+pub fn read_gauges(path: &str) -> io::Result<Vec<GaugeObservation>> {
+    parse_gauges(&fs::read_to_string(path)?)
+}
+
+// CSV lines of "id,lon,lat,observed mm" with signed longitudes. A header line, blank lines and lines
+// starting with # are skipped
+pub fn parse_gauges(text: &str) -> io::Result<Vec<GaugeObservation>> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .filter(|(number, line)| !(*number == 1 && is_header(line)))
+        .map(|(number, line)| {
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            let numbers = fields
+                .get(1..4)
+                .map(|f| {
+                    f.iter()
+                        .map(|n| n.parse::<f64>())
+                        .collect::<Result<Vec<f64>, _>>()
+                })
+                .and_then(|n| n.ok());
+
+            match numbers.as_deref() {
+                Some([lon, lat, observed]) => Ok(GaugeObservation {
+                    station: Station::new(fields[0], *lon, *lat),
+                    observed: *observed,
+                }),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected id,lon,lat,observed", number),
+                )),
+            }
+        })
+        .collect()
+}
+
+fn is_header(line: &str) -> bool {
+    line.split(',')
+        .nth(1)
+        .is_some_and(|field| field.trim().parse::<f64>().is_err())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+    use crate::hrap::{hrap_to_latlon, signed_longitude};
+    use crate::utils::MISSING;
+
+    #[test]
+    fn verify_test() {
+        let header = Header::new(367, 263, 2, 2);
+        let data = XmrgData::new(header, None, vec![vec![1.0, 2.0], vec![4.0, MISSING]]);
+
+        let gauge = |column: usize, row: usize| {
+            let hrap = header.hrap_point(column, row);
+            let p = hrap_to_latlon(hrap.x, hrap.y);
+            format!("{},{}", signed_longitude(p.x), p.y)
+        };
+        let text = format!(
+            "id,lon,lat,mm\nA,{},2.0\nB,{},2.0\nC,{},3.0\nD,{},1.0\n",
+            gauge(0, 0),
+            gauge(1, 0),
+            gauge(0, 1),
+            gauge(1, 1)
+        );
+
+        let gauges = parse_gauges(&text).unwrap();
+        assert_eq!(gauges.len(), 4);
+
+        let verification = verify(&data, &gauges, Sampling::Nearest);
+        // D has no radar value
+        assert_eq!(verification.count, 3);
+        assert!((verification.bias - 0.0).abs() < 1e-9);
+        assert!((verification.mae - 2.0 / 3.0).abs() < 1e-9);
+        assert!((verification.rmse - (2.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert!((verification.correlation - 15.0 / 252f64.sqrt()).abs() < 1e-6);
+        assert!(verification.pairs[3].csv_row().ends_with("1.00,-999.00,"));
+
+        assert!(parse_gauges("A,1,2\n").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::datetime::DateSegments;
+use crate::mask::BasinMask;
+use crate::stack::GridStack;
+use crate::utils::MISSING;
+use crate::XmrgData;
+
+use std::io;
+
+// rain volume over a basin, the depth of each basin cell times its ground area. Cells without data add nothing
+
+const CUBIC_METERS_PER_ACRE_FOOT: f64 = 1233.48183754752;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VolumeUnit {
+    CubicMeters,
+    AcreFeet,
+}
+
+impl VolumeUnit {
+    pub fn from_cubic_meters(&self, cubic_meters: f64) -> f64 {
+        match self {
+            VolumeUnit::CubicMeters => cubic_meters,
+            VolumeUnit::AcreFeet => cubic_meters / CUBIC_METERS_PER_ACRE_FOOT,
+        }
+    }
+}
+
+impl XmrgData {
+    pub fn volume(&self, mask: &BasinMask, unit: VolumeUnit) -> io::Result<f64> {
+        mask.check_header(self)?;
+        Ok(volume_of(&self.values, &cell_areas(mask), unit))
+    }
+}
+
+// the volume of each hour of a stack, MISSING for hours with no grid
+pub fn volume_series(
+    stack: &GridStack,
+    mask: &BasinMask,
+    unit: VolumeUnit,
+) -> io::Result<Vec<(DateSegments, f64)>> {
+    if stack.header != mask.header {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "stack header {:?} does not match the header of basin {}",
+                stack.header, mask.id
+            ),
+        ));
+    }
+
+    let areas = cell_areas(mask);
+    Ok(stack
+        .times
+        .iter()
+        .enumerate()
+        .map(|(i, time)| {
+            let volume = if stack.is_missing(i) {
+                MISSING
+            } else {
+                volume_of(&stack.values[i], &areas, unit)
+            };
+            (*time, volume)
+        })
+        .collect())
+}
+
+// the total of the hours with a volume
+pub fn total_volume(series: &[(DateSegments, f64)]) -> f64 {
+    series
+        .iter()
+        .map(|(_, volume)| *volume)
+        .filter(|volume| *volume >= 0.0)
+        .sum()
+}
+
+// (row, column, m2) of each basin cell
+fn cell_areas(mask: &BasinMask) -> Vec<(usize, usize, f64)> {
+    mask.cells
+        .iter()
+        .enumerate()
+        .flat_map(|(row, cells)| {
+            cells
+                .iter()
+                .enumerate()
+                .filter(|(_, inside)| **inside)
+                .map(move |(column, _)| (row, column))
+        })
+        .map(|(row, column)| (row, column, mask.header.cell_area_km2(column, row) * 1.0e6))
+        .collect()
+}
+
+fn volume_of(values: &[Vec<f64>], areas: &[(usize, usize, f64)], unit: VolumeUnit) -> f64 {
+    let cubic_meters: f64 = areas
+        .iter()
+        .map(|(row, column, area)| (values[*row][*column], area))
+        .filter(|(depth, _)| *depth >= 0.0)
+        .map(|(depth, area)| depth / 1000.0 * area)
+        .sum();
+    unit.from_cubic_meters(cubic_meters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::Header;
+
+    #[test]
+    fn volume_test() {
+        let header = Header::new(367, 263, 2, 1);
+        let mask = BasinMask::from_cells("B", header, &[(367, 263), (368, 263)]);
+        let data = XmrgData::new(header, None, vec![vec![10.0, MISSING]]);
+
+        let area = header.cell_area_km2(0, 0) * 1.0e6;
+        let cubic_meters = data.volume(&mask, VolumeUnit::CubicMeters).unwrap();
+        assert!((cubic_meters - 0.01 * area).abs() < 1e-6);
+
+        let acre_feet = data.volume(&mask, VolumeUnit::AcreFeet).unwrap();
+        assert!((acre_feet * CUBIC_METERS_PER_ACRE_FOOT - cubic_meters).abs() < 1e-6);
+
+        let stack = GridStack::from_grids(vec![
+            (DateSegments::new(2020, 1, 1, 1), data),
+            (
+                DateSegments::new(2020, 1, 1, 3),
+                XmrgData::new(header, None, vec![vec![10.0, 0.0]]),
+            ),
+        ])
+        .unwrap();
+        let series = volume_series(&stack, &mask, VolumeUnit::CubicMeters).unwrap();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[1].1, MISSING);
+        assert!((total_volume(&series) - 2.0 * cubic_meters).abs() < 1e-6);
+    }
+}
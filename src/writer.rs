@@ -0,0 +1,158 @@
+use crate::headers::{Metadata, OperSys};
+use crate::utils::from_mm;
+use crate::XmrgData;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+// writes little endian XMRG using the same fortran record layout read by XmrgReader
+// values are stored as hundredths of a millimeter, a value above 327.67mm or a negative value other than
+// MISSING is an InvalidData error
+// XMRG does not store the HRAP subdivision factor. Reading infers it from a window beyond the national HRAP grid
+// or a file name containing "shrap" (see Header::factor_from_window and factor_from_path), so a small SHRAP
+// window written to any other name reads back as HRAP
+
+pub fn write_xmrg(path: &str, data: &XmrgData) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_xmrg_to(&mut writer, data)?;
+    writer.flush()
+}
+
+pub fn write_xmrg_to<W: Write>(writer: &mut W, data: &XmrgData) -> io::Result<()> {
+    let header = [
+        data.header.xor(),
+        data.header.yor(),
+        data.header.columns(),
+        data.header.rows(),
+    ];
+    let header_bytes: Vec<u8> = header.iter().flat_map(|n| n.to_le_bytes()).collect();
+    write_record(writer, &header_bytes)?;
+
+    if let Some(metadata) = &data.metadata {
+        write_record(writer, &metadata_bytes(metadata))?;
+    }
+
+    for row in &data.values {
+        let mut row_bytes = Vec::with_capacity(row.len() * 2);
+        for value in row {
+            row_bytes.extend(from_mm(*value)?.to_le_bytes());
+        }
+        write_record(writer, &row_bytes)?;
+    }
+
+    Ok(())
+}
+
+fn write_record<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    let len = (bytes.len() as i32).to_le_bytes();
+    writer.write_all(&len)?;
+    writer.write_all(bytes)?;
+    writer.write_all(&len)
+}
+
+fn fixed_width(s: &str, width: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = s.bytes().take(width).collect();
+    bytes.resize(width, b' ');
+    bytes
+}
+
+fn metadata_bytes(metadata: &Metadata) -> Vec<u8> {
+    let user_id = metadata.user_id().unwrap_or_default();
+    let process_flag = metadata.process_flag().unwrap_or_default();
+
+    let mut bytes = match metadata {
+        Metadata::Header5_2_2(_) => {
+            let os = match metadata.os() {
+                Some(OperSys::HP) => "HP",
+                Some(OperSys::LX) => "LX",
+                _ => "",
+            };
+            [fixed_width(os, 2), fixed_width(&user_id, 8)].concat()
+        }
+        _ => fixed_width(&user_id, 10),
+    };
+    bytes.extend(fixed_width(&metadata.datetime(), 20));
+    bytes.extend(fixed_width(&process_flag, 8));
+
+    // the 38 byte record ends at the process flag
+    if let Metadata::Header1997(_) = metadata {
+        return bytes;
+    }
+
+    bytes.extend(fixed_width(
+        &metadata.valid_datetime().unwrap_or_default(),
+        20,
+    ));
+    bytes.extend(metadata.max_value().unwrap_or_default().to_le_bytes());
+    bytes.extend(metadata.version().unwrap_or_default().to_le_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algebra::constant_grid;
+    use crate::datetime::DateSegments;
+    use crate::headers::Header;
+    use crate::reader::XmrgReader;
+
+    #[test]
+    fn write_read_round_trip_test() {
+        let header = Header::new(367, 263, 3, 2);
+        let valid = DateSegments::new(2020, 6, 1, 12);
+        let values = vec![vec![0.0, 1.25, -999.0], vec![300.0, 0.01, 2.0]];
+        let metadata = Metadata::for_product(valid, "QPE01", &values);
+        let data = XmrgData::new(header, Some(metadata), values);
+
+        let mut bytes = Vec::new();
+        write_xmrg_to(&mut bytes, &data).unwrap();
+
+        // products are reproducible, writing the same grid again gives the same bytes
+        let again = XmrgData::new(
+            header,
+            Some(Metadata::for_product(valid, "QPE01", &data.values)),
+            data.values.clone(),
+        );
+        let mut again_bytes = Vec::new();
+        write_xmrg_to(&mut again_bytes, &again).unwrap();
+        assert_eq!(bytes, again_bytes);
+
+        let read = XmrgReader::new(&bytes[..]).unwrap().into_data().unwrap();
+
+        assert_eq!(read.header, header);
+        assert_eq!(read.valid_time(), Some(valid));
+        assert_eq!(read.metadata.unwrap().max_value(), Some(30000));
+        assert_eq!(read.values[0], vec![0.0, 1.25, -999.0]);
+        assert_eq!(read.values[1], vec![300.0, 0.01, 2.0]);
+    }
+
+    #[test]
+    fn write_read_shrap_test() {
+        // the factor is not written, a window beyond the HRAP grid implies SHRAP when read
+        let header = Header::new(4000, 1000, 2, 1).with_factor(4).unwrap();
+        let data = XmrgData::new(header, None, vec![vec![1.0, 2.0]]);
+
+        let mut bytes = Vec::new();
+        write_xmrg_to(&mut bytes, &data).unwrap();
+
+        let read = XmrgReader::new(&bytes[..]).unwrap().into_data().unwrap();
+        assert_eq!(read.header, header);
+    }
+
+    #[test]
+    fn write_overflow_test() {
+        let data = XmrgData::new(Header::new(367, 263, 2, 1), None, vec![vec![1.0, 400.0]]);
+
+        let error = write_xmrg_to(&mut Vec::new(), &data).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("400"));
+
+        // negative differences are not missing values
+        let difference = data.subtract(&constant_grid(data.header, 2.0)).unwrap();
+        let error = write_xmrg_to(&mut Vec::new(), &difference).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("-1 mm"));
+    }
+}
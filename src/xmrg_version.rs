@@ -1,20 +1,29 @@
+// the layout of the second record, told apart by its byte count.
 // see the second record section of https://www.nws.noaa.gov/oh/hrl/misc/xmrg.pdf
 #[derive(Debug, Eq, PartialEq)]
 pub enum XmrgVersion {
-    Pre1997,
+    Pre1997,   // no metadata, the second record is the first row of data
+    Build1997, // 38 bytes: user id, saved date time and process flag
+    // 66 bytes: the 1997 fields then valid date time, max value and version number. Build 5.2.2 files have
+    // the same length but start with the operating system, see is_build_5_2_2
     Build4_2,
-    Build5_2_2,
 }
 
 pub fn get_xmrg_version(byte_count: i32, max_x: i32) -> Option<XmrgVersion> {
     match byte_count {
-        66 => Some(XmrgVersion::Build5_2_2),
-        38 => Some(XmrgVersion::Build4_2), // a 37 byte version may be valid. Consider adding
+        66 => Some(XmrgVersion::Build4_2),
+        38 => Some(XmrgVersion::Build1997), // a 37 byte version may be valid. Consider adding
         n if n == max_x * 2 => Some(XmrgVersion::Pre1997),
         _ => None,
     }
 }
 
+// build 5.2.2 put a 2 character operating system, HP or LX, in front of an 8 character user id, where
+// build 4.2 has a 10 character user id
+pub fn is_build_5_2_2(record: &[u8]) -> bool {
+    record.starts_with(b"HP") || record.starts_with(b"LX")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -26,10 +35,10 @@ mod tests {
         let second_byte_count = 38;
 
         let v1 = get_xmrg_version(first_byte_count, columns);
-        assert_eq!(v1, Some(XmrgVersion::Build5_2_2));
+        assert_eq!(v1, Some(XmrgVersion::Build4_2));
 
         let v2 = get_xmrg_version(second_byte_count, columns);
-        assert_eq!(v2, Some(XmrgVersion::Build4_2));
+        assert_eq!(v2, Some(XmrgVersion::Build1997));
 
         let v3 = get_xmrg_version(columns * 2, columns);
         assert_eq!(v3, Some(XmrgVersion::Pre1997));